@@ -0,0 +1,72 @@
+use anyhow::{bail, Context};
+use clap::{arg, App};
+
+use crate::commands::{input_path, output_dir, Command, Preview, Sat, TerrainRGB};
+
+use std::ffi::OsString;
+use std::fs;
+
+use std::time::Instant;
+
+pub struct All {}
+
+impl Command for All {
+    fn register(&self) -> App<'static> {
+        App::new("all")
+            .about("Build satellite and Terrain-RGB tiles and the preview images into one directory with a map.json.")
+            .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory (or MEH_INPUT)").required(false))
+            .arg(arg!(-o --output <OUTPUT_DIR> "Path to output directory (or MEH_OUTPUT, default: out/<worldName>/all)").required(false))
+            .arg(arg!([INPUT] "Same as --input"))
+            .arg(arg!([OUTPUT] "Same as --output"))
+            .arg(arg!(-y --yes "Overwrite tiles of a previous run without asking"))
+    }
+    fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
+        let start = Instant::now();
+
+        let input_path = &input_path(args)?;
+        let output_path = &output_dir(args, input_path, "all")?;
+
+        if !output_path.is_dir() {
+            bail!("Output path is not a directory");
+        }
+
+        let manifest_path = output_path.join("map.json");
+        let steps: [(&dyn Command, &str, bool); 3] = [
+            (&Sat {}, "sat", true),
+            (&TerrainRGB {}, "terrain", true),
+            (&Preview {}, "preview", false),
+        ];
+
+        for (command, dir, asks_to_overwrite) in steps {
+            let app = command.register();
+            let name = app.get_name().to_owned();
+            let sub_output_path = output_path.join(dir);
+            fs::create_dir_all(&sub_output_path)
+                .with_context(|| format!("Failed to create {}", sub_output_path.display()))?;
+
+            let mut sub_args: Vec<OsString> = vec![
+                name.clone().into(),
+                "--input".into(),
+                input_path.into(),
+                "--output".into(),
+                sub_output_path.into(),
+                "--manifest".into(),
+                manifest_path.clone().into(),
+            ];
+            if asks_to_overwrite && args.is_present("yes") {
+                sub_args.push("--yes".into());
+            }
+
+            info!("Running {}", name);
+            let sub_matches = app.try_get_matches_from(sub_args)?;
+            command
+                .run(&sub_matches)
+                .with_context(|| format!("{} failed", name))?;
+        }
+
+        info!("Wrote {}", manifest_path.display());
+        crate::output::finished(start);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+use anyhow::{bail, Context};
+use clap::{arg, App};
+use flate2::{write::GzEncoder, Compression};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde_json::{json, Value};
+
+use crate::commands::{input_path, output_dir, Command};
+use crate::dem::{contour_lines, load_map_dem, reconcile_world_size, DEMRaster};
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use std::time::Instant;
+
+pub struct Contours {}
+
+impl Command for Contours {
+    fn register(&self) -> App<'static> {
+        App::new("contours")
+            .about("Write the contour lines of the DEM as GeoJSON, one file per interval.")
+            .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory (or MEH_INPUT)").required(false))
+            .arg(arg!(-o --output <OUTPUT_DIR> "Path to output directory (or MEH_OUTPUT, default: out/<worldName>/contours)").required(false))
+            .arg(arg!([INPUT] "Same as --input"))
+            .arg(arg!([OUTPUT] "Same as --output"))
+            .arg(
+                arg!(--intervals <METERS> "Comma separated list of contour intervals")
+                    .required(false)
+                    .default_value("10,50,100"),
+            )
+            .arg(arg!(--gzip "Write gzipped .geojson.gz files"))
+            .arg(arg!(--"no-dem-cache" "Always parse the DEM instead of using/writing dem.cache"))
+            .arg(arg!(--"rescale-dem" "Scale the DEM to span the worldSize of meta.json"))
+    }
+    fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
+        let start = Instant::now();
+
+        let input_path = &input_path(args)?;
+        let output_path = &output_dir(args, input_path, "contours")?;
+
+        if !output_path.is_dir() {
+            bail!("Output path is not a directory");
+        }
+
+        let intervals = parse_intervals(args.value_of("intervals").unwrap())?;
+        let gzip = args.is_present("gzip");
+
+        step!("Loading meta.json");
+        let meta = crate::metajson::from_file(&input_path.join("meta.json"))?;
+        done!("Loaded meta.json");
+
+        let now = Instant::now();
+        step!("Loading DEM");
+        let mut dem = load_map_dem(input_path, !args.is_present("no-dem-cache"))?;
+        done!("Loaded DEM in {}ms", now.elapsed().as_millis());
+        reconcile_world_size(&mut dem, meta.world_size, args.is_present("rescale-dem"));
+
+        let now = Instant::now();
+        step!("Tracing contour lines");
+        let (min, max) = match elevation_range(&dem) {
+            Some(range) => range,
+            None => bail!("DEM has no elevation data"),
+        };
+        // every level is traced once, even if it's part of multiple intervals
+        let lines: BTreeMap<u32, Vec<Vec<(f32, f32)>>> = intervals
+            .iter()
+            .flat_map(|interval| levels(*interval, min, max))
+            .map(f32::to_bits)
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(|level| (level, contour_lines(&dem, f32::from_bits(level))))
+            .collect();
+        done!(
+            "Traced {} contour levels in {}ms",
+            lines.len(),
+            now.elapsed().as_millis()
+        );
+
+        // in-game coordinates start at the lower left corner of the DEM (which is
+        // usually at an easting of 200000)
+        let (left, bottom) = dem.lower_left();
+
+        for interval in intervals {
+            let now = Instant::now();
+            step!("Writing contours of {}m interval", interval);
+
+            let features: Vec<Value> = levels(interval, min, max)
+                .into_iter()
+                .flat_map(|level| {
+                    lines[&level.to_bits()].iter().map(move |line| {
+                        let closed = line.len() > 2 && line.first() == line.last();
+                        json!({
+                            "type": "Feature",
+                            "properties": {
+                                "elevation": level + meta.elevation_offset,
+                                "dem_elevation": level,
+                                "closed": closed,
+                                "direction": uphill_side(line, closed),
+                            },
+                            "geometry": {
+                                "type": "LineString",
+                                "coordinates": line
+                                    .iter()
+                                    .map(|(x, y)| [round(x - left), round(y - bottom)])
+                                    .collect::<Vec<_>>(),
+                            },
+                        })
+                    })
+                })
+                .collect();
+            let count = features.len();
+
+            let name = format!(
+                "contours_{}.geojson{}",
+                interval,
+                if gzip { ".gz" } else { "" }
+            );
+            write_geojson(
+                &output_path.join(&name),
+                &json!({ "type": "FeatureCollection", "features": features }),
+                gzip,
+            )?;
+            done!(
+                "Wrote {} lines to {} in {}ms",
+                count,
+                name,
+                now.elapsed().as_millis()
+            );
+        }
+
+        crate::output::finished(start);
+
+        Ok(())
+    }
+}
+
+fn parse_intervals(value: &str) -> anyhow::Result<Vec<f32>> {
+    let intervals = value
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .context("Invalid contour interval")?;
+
+    if intervals.iter().any(|interval| *interval <= 0.0) {
+        bail!("Contour intervals have to be greater than zero");
+    }
+
+    Ok(intervals)
+}
+
+/// Lowest and highest elevation of all cells with data.
+fn elevation_range(dem: &DEMRaster) -> Option<(f32, f32)> {
+    let no_data = dem.no_data_value();
+
+    dem.get_data()
+        .iter()
+        .filter(|z| **z != no_data)
+        .fold(None, |range, z| match range {
+            None => Some((*z, *z)),
+            Some((min, max)) => Some((min.min(*z), max.max(*z))),
+        })
+}
+
+/// All multiples of `interval` from `min` to `max`.
+fn levels(interval: f32, min: f32, max: f32) -> Vec<f32> {
+    let first = (min / interval).ceil() as i64;
+    let last = (max / interval).floor() as i64;
+
+    (first..=last).map(|n| n as f32 * interval).collect()
+}
+
+/// Side of `line` with the higher terrain. Lines run with it on their right,
+/// which makes clockwise rings enclose peaks and counterclockwise rings
+/// enclose depressions.
+fn uphill_side(line: &[(f32, f32)], closed: bool) -> &'static str {
+    if !closed {
+        return "right";
+    }
+
+    // twice the enclosed area, negative if clockwise
+    let area: f64 = line
+        .windows(2)
+        .map(|pair| {
+            let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+            x1 as f64 * y2 as f64 - x2 as f64 * y1 as f64
+        })
+        .sum();
+
+    if area < 0.0 {
+        "inside"
+    } else {
+        "outside"
+    }
+}
+
+/// Rounds world positions to centimeters, which keeps the GeoJSON readable.
+fn round(value: f32) -> f64 {
+    (value as f64 * 100.0).round() / 100.0
+}
+
+fn write_geojson(path: &Path, geojson: &Value, gzip: bool) -> anyhow::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    if gzip {
+        let mut encoder = GzEncoder::new(out, Compression::default());
+        serde_json::to_writer(&mut encoder, geojson)?;
+        encoder.finish()?.flush()?;
+    } else {
+        serde_json::to_writer(&mut out, geojson)?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{levels, parse_intervals, uphill_side};
+
+    #[test]
+    fn lists_levels_within_range() {
+        assert_eq!(vec![-10.0, 0.0, 10.0, 20.0], levels(10.0, -15.0, 20.0));
+        assert_eq!(vec![50.0], levels(50.0, 12.0, 99.0));
+        assert!(levels(100.0, 12.0, 99.0).is_empty());
+    }
+
+    #[test]
+    fn finds_uphill_side_of_rings() {
+        let clockwise = [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)];
+        let counterclockwise: Vec<_> = clockwise.iter().rev().copied().collect();
+
+        assert_eq!("inside", uphill_side(&clockwise, true));
+        assert_eq!("outside", uphill_side(&counterclockwise, true));
+        assert_eq!("right", uphill_side(&clockwise[..3], false));
+    }
+
+    #[test]
+    fn parses_intervals() {
+        assert_eq!(vec![10.0, 2.5], parse_intervals("10, 2.5").unwrap());
+        assert!(parse_intervals("10,0").is_err());
+        assert!(parse_intervals("ten").is_err());
+    }
+}
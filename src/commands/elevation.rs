@@ -0,0 +1,140 @@
+use anyhow::{bail, Context};
+use clap::{arg, App};
+use serde::Serialize;
+
+use crate::commands::{input_path, Command};
+use crate::dem::{load_map_dem, reconcile_world_size, DEMRaster};
+
+#[derive(Debug, Serialize, PartialEq)]
+struct Elevation {
+    x: f64,
+    y: f64,
+    /// `None` outside of the DEM or next to nodata cells
+    elevation: Option<f32>,
+}
+
+pub struct ElevationQuery {}
+
+impl Command for ElevationQuery {
+    fn register(&self) -> App<'static> {
+        App::new("elevation")
+            .about("Print the elevation (including elevationOffset) at in-game positions.")
+            .arg(
+                arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory (or MEH_INPUT)")
+                    .required(false),
+            )
+            .arg(arg!([INPUT] "Same as --input"))
+            .arg(arg!(--at <POSITIONS> "Comma separated in-game positions like 100,200,300,400"))
+            .arg(arg!(--json "Print a JSON array instead of one line per position"))
+            .arg(arg!(--"no-dem-cache" "Always parse the DEM instead of using/writing dem.cache"))
+            .arg(arg!(--"rescale-dem" "Scale the DEM to span the worldSize of meta.json"))
+    }
+    fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
+        let input_path = &input_path(args)?;
+        let positions = parse_positions(args.value_of("at").unwrap())?;
+
+        // only the elevations are printed to stdout, so they can be piped
+        // into scripts
+        crate::output::set_stderr(true);
+        let meta = crate::metajson::from_file(&input_path.join("meta.json"))?;
+        let mut dem = load_map_dem(input_path, !args.is_present("no-dem-cache"))?;
+        reconcile_world_size(&mut dem, meta.world_size, args.is_present("rescale-dem"));
+
+        let elevations = query(&dem, &positions, meta.elevation_offset);
+
+        if args.is_present("json") {
+            println!("{}", serde_json::to_string(&elevations)?);
+            return Ok(());
+        }
+
+        for e in elevations {
+            match e.elevation {
+                Some(z) => println!("{},{}: {}", e.x, e.y, z),
+                None => println!("{},{}: -", e.x, e.y),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a flat list of coordinates like `100,200,300,400` into positions.
+fn parse_positions(value: &str) -> anyhow::Result<Vec<(f64, f64)>> {
+    let coords = value
+        .split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .context("Invalid position")?;
+
+    if coords.len() % 2 != 0 {
+        bail!("Positions have to be given as X,Y pairs");
+    }
+
+    Ok(coords.chunks(2).map(|pair| (pair[0], pair[1])).collect())
+}
+
+fn query(dem: &DEMRaster, positions: &[(f64, f64)], elevation_offset: f32) -> Vec<Elevation> {
+    // in-game coordinates start at the lower left corner of the DEM (which is
+    // usually at an easting of 200000)
+    let (left, bottom) = dem.lower_left();
+
+    positions
+        .iter()
+        .map(|&(x, y)| Elevation {
+            x,
+            y,
+            elevation: dem
+                .sample(left + x as f32, bottom + y as f32)
+                .map(|z| z + elevation_offset),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_positions, query, Elevation};
+    use crate::dem::raster::{DEMRaster, Origin};
+
+    #[test]
+    fn parses_pairs() {
+        assert_eq!(
+            vec![(1.0, 2.0), (3.5, 4.0)],
+            parse_positions("1,2, 3.5,4").unwrap()
+        );
+        assert!(parse_positions("1,2,3").is_err());
+        assert!(parse_positions("1,a").is_err());
+    }
+
+    #[test]
+    fn queries_offset_elevations() {
+        let dem = DEMRaster::new(
+            2,
+            2,
+            Origin::Corner(200000.0, 0.0),
+            10.0,
+            -9999.0,
+            vec![0.0, 10.0, 20.0, 30.0],
+        );
+
+        assert_eq!(
+            vec![
+                Elevation {
+                    x: 0.0,
+                    y: 20.0,
+                    elevation: Some(5.0)
+                },
+                Elevation {
+                    x: 5.0,
+                    y: 15.0,
+                    elevation: Some(20.0)
+                },
+                Elevation {
+                    x: 50.0,
+                    y: 0.0,
+                    elevation: None
+                },
+            ],
+            query(&dem, &[(0.0, 20.0), (5.0, 15.0), (50.0, 0.0)], 5.0)
+        );
+    }
+}
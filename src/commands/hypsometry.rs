@@ -0,0 +1,141 @@
+use anyhow::{bail, Context};
+use clap::{arg, App};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::commands::{input_path, output_dir, Command};
+use crate::dem::{hypsometry, load_map_dem, reconcile_world_size, ElevationDistribution};
+use crate::utils::encode_png;
+
+use std::fs::File;
+use std::io::BufWriter;
+
+use std::time::Instant;
+
+const CHART_WIDTH: u32 = 800;
+const CHART_HEIGHT: u32 = 400;
+const BAR_COLOR: Rgba<u8> = Rgba([120, 160, 200, 255]);
+const CURVE_COLOR: Rgba<u8> = Rgba([200, 40, 40, 255]);
+const BACKGROUND_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+pub struct Hypsometry {}
+
+impl Command for Hypsometry {
+    fn register(&self) -> App<'static> {
+        App::new("hypsometry")
+            .about("Write the elevation histogram and hypsometric curve of the DEM as JSON.")
+            .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory (or MEH_INPUT)").required(false))
+            .arg(arg!(-o --output <OUTPUT_DIR> "Path to output directory (or MEH_OUTPUT, default: out/<worldName>/hypsometry)").required(false))
+            .arg(arg!([INPUT] "Same as --input"))
+            .arg(arg!([OUTPUT] "Same as --output"))
+            .arg(
+                arg!(--"bin-size" <METERS> "Elevation range of each histogram bin")
+                    .required(false)
+                    .default_value("10"),
+            )
+            .arg(arg!(--chart "Also render hypsometry.png with the histogram and the curve"))
+            .arg(arg!(--"no-dem-cache" "Always parse the DEM instead of using/writing dem.cache"))
+            .arg(arg!(--"rescale-dem" "Scale the DEM to span the worldSize of meta.json"))
+    }
+    fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
+        let start = Instant::now();
+
+        let input_path = &input_path(args)?;
+        let output_path = &output_dir(args, input_path, "hypsometry")?;
+
+        if !output_path.is_dir() {
+            bail!("Output path is not a directory");
+        }
+
+        let bin_size = args
+            .value_of("bin-size")
+            .unwrap()
+            .parse::<f32>()
+            .context("Invalid bin size")?;
+        if bin_size <= 0.0 {
+            bail!("Bin size has to be greater than zero");
+        }
+
+        step!("Loading meta.json");
+        let meta = crate::metajson::from_file(&input_path.join("meta.json"))?;
+        done!("Loaded meta.json");
+
+        let now = Instant::now();
+        step!("Loading DEM");
+        let mut dem = load_map_dem(input_path, !args.is_present("no-dem-cache"))?;
+        done!("Loaded DEM in {}ms", now.elapsed().as_millis());
+        reconcile_world_size(&mut dem, meta.world_size, args.is_present("rescale-dem"));
+
+        let now = Instant::now();
+        step!("Calculating hypsometry");
+        let result = match hypsometry(&dem, bin_size, meta.elevation_offset) {
+            Some(result) => result,
+            None => bail!("DEM contains no elevation data"),
+        };
+        let file = BufWriter::new(File::create(output_path.join("hypsometry.json"))?);
+        serde_json::to_writer(file, &result)?;
+        done!("Wrote hypsometry.json in {}ms", now.elapsed().as_millis());
+        info!(
+            "Elevation range: {}m - {}m in {} bins",
+            result.min,
+            result.max,
+            result.histogram.len()
+        );
+
+        if args.is_present("chart") {
+            let now = Instant::now();
+            step!("Rendering hypsometry.png");
+            let chart = render_chart(&result);
+            encode_png(&output_path.join("hypsometry.png"), &chart)
+                .map_err(|e| anyhow::anyhow!(e))?;
+            done!("Rendered hypsometry.png in {}ms", now.elapsed().as_millis());
+        }
+
+        crate::output::finished(start);
+
+        Ok(())
+    }
+}
+
+/// Draws the histogram as bars (scaled to the largest bin) and the
+/// hypsometric curve as a line on top of it. Elevation increases to the right.
+fn render_chart(result: &ElevationDistribution) -> DynamicImage {
+    let mut img = RgbaImage::from_pixel(CHART_WIDTH, CHART_HEIGHT, BACKGROUND_COLOR);
+
+    let bins = &result.histogram;
+    let max_count = bins.iter().map(|bin| bin.count).max().unwrap_or(1).max(1);
+    let range = (result.max - result.min).max(f32::EPSILON);
+
+    for x in 0..CHART_WIDTH {
+        let elevation = result.min + x as f32 / (CHART_WIDTH - 1) as f32 * range;
+        let bin = bins
+            .iter()
+            .rev()
+            .find(|bin| bin.elevation <= elevation)
+            .unwrap_or(&bins[0]);
+        let bar_height = (bin.count as f32 / max_count as f32 * CHART_HEIGHT as f32).round() as u32;
+        for y in CHART_HEIGHT - bar_height..CHART_HEIGHT {
+            img.put_pixel(x, y, BAR_COLOR);
+        }
+    }
+
+    let to_pixel = |elevation: f32, area_above: f32| {
+        let x = (elevation - result.min) / range * (CHART_WIDTH - 1) as f32;
+        let y = (1.0 - area_above) * (CHART_HEIGHT - 1) as f32;
+        (x, y)
+    };
+
+    for points in result.hypsometric_curve.windows(2) {
+        let (x1, y1) = to_pixel(points[0].elevation, points[0].area_above);
+        let (x2, y2) = to_pixel(points[1].elevation, points[1].area_above);
+
+        let steps = (x2 - x1).abs().max((y2 - y1).abs()).ceil().max(1.0) as u32;
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            let x = (x1 + (x2 - x1) * t).round() as u32;
+            let y = (y1 + (y2 - y1) * t).round() as u32;
+            img.put_pixel(x, y, CURVE_COLOR);
+        }
+    }
+
+    DynamicImage::ImageRgba8(img)
+}
@@ -1,10 +1,41 @@
+mod all;
+mod contours;
+mod elevation;
+mod hypsometry;
+mod mounts;
 mod preview;
+mod profile;
+mod prune;
+mod ruggedness;
 mod sat;
+mod serve;
 mod terrain_rgb;
+mod tint;
 
+pub use all::All;
+pub use contours::Contours;
+pub use elevation::ElevationQuery;
+pub use hypsometry::Hypsometry;
+pub use mounts::Mounts;
 pub use preview::Preview;
+pub use profile::Profile;
+pub use prune::Prune;
+pub use ruggedness::Ruggedness;
 pub use sat::Sat;
+pub use serve::Serve;
 pub use terrain_rgb::TerrainRGB;
+pub use tint::Tint;
+
+use anyhow::{bail, Context};
+use clap::arg;
+
+use crate::utils::{
+    TileFormats, TilePathTemplate, TileStats, TileWriter, Watermark, DEFAULT_TILE_PATH,
+};
+use std::collections::BTreeSet;
+use std::io::{BufRead, IsTerminal, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 
 pub trait Command {
     fn register(&self) -> clap::App<'static>;
@@ -12,3 +43,390 @@ pub trait Command {
         unimplemented!();
     }
 }
+
+/// `--input`, the positional INPUT or the MEH_INPUT environment variable.
+fn input_path(args: &clap::ArgMatches) -> anyhow::Result<PathBuf> {
+    io_path(args, "input", "INPUT", "MEH_INPUT")
+}
+
+/// `--output`, the positional OUTPUT or the MEH_OUTPUT environment variable.
+fn output_path(args: &clap::ArgMatches) -> anyhow::Result<PathBuf> {
+    io_path(args, "output", "OUTPUT", "MEH_OUTPUT")
+}
+
+/// Like [`output_path`], but defaults to `./out/<worldName>/<command>/`.
+/// The directory is created if it doesn't exist yet.
+fn output_dir(
+    args: &clap::ArgMatches,
+    input_path: &Path,
+    command: &str,
+) -> anyhow::Result<PathBuf> {
+    output_dir_in(args, input_path, command, Path::new("out"))
+}
+
+/// [`output_dir`] with the default path below `default_base` instead of `./out`.
+fn output_dir_in(
+    args: &clap::ArgMatches,
+    input_path: &Path,
+    command: &str,
+    default_base: &Path,
+) -> anyhow::Result<PathBuf> {
+    let path = match given_path(args, "output", "OUTPUT", "MEH_OUTPUT") {
+        Some(path) => path,
+        None => {
+            let meta = crate::metajson::from_file(&input_path.join("meta.json"))?;
+            let path = default_base.join(&meta.world_name).join(command);
+            info!("Writing to {}", path.display());
+            path
+        }
+    };
+
+    std::fs::create_dir_all(&path)
+        .with_context(|| format!("Failed to create output directory {}", path.display()))?;
+
+    Ok(path)
+}
+
+fn io_path(
+    args: &clap::ArgMatches,
+    name: &str,
+    positional: &str,
+    env: &str,
+) -> anyhow::Result<PathBuf> {
+    given_path(args, name, positional, env)
+        .with_context(|| format!("Pass --{} (or {} or set {})", name, positional, env))
+}
+
+fn given_path(
+    args: &clap::ArgMatches,
+    name: &str,
+    positional: &str,
+    env: &str,
+) -> Option<PathBuf> {
+    args.value_of(name)
+        .or_else(|| args.value_of(positional))
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os(env).map(PathBuf::from))
+}
+
+/// Parses `--<name>`, a count which has to be at least one.
+fn parse_count(args: &clap::ArgMatches, name: &str) -> anyhow::Result<Option<usize>> {
+    let count = match args.value_of(name) {
+        Some(count) => count
+            .parse::<usize>()
+            .with_context(|| format!("Invalid --{}", name))?,
+        None => return Ok(None),
+    };
+
+    if count == 0 {
+        bail!("--{} has to be at least 1", name);
+    }
+
+    Ok(Some(count))
+}
+
+/// Adds the arguments of [`tile_writer_from_args`] and
+/// [`confirm_tile_overwrite`], which all commands building tiles share.
+fn tile_writer_args(app: clap::App<'static>) -> clap::App<'static> {
+    app.arg(
+        arg!(--"tile-path" <TEMPLATE> "Layout of the tiles within the output directory")
+            .required(false)
+            .default_value(DEFAULT_TILE_PATH),
+    )
+    .arg(
+        arg!(--format <FORMATS> "Comma separated list of tile formats (png, jpeg, webp), optionally per LOD range like 0-4:jpeg,5-:png")
+            .required(false)
+            .default_value("png"),
+    )
+    .arg(
+        arg!(--"max-concurrent-writes" <COUNT> "Maximum number of tiles written at the same time")
+            .required(false),
+    )
+    .arg(
+        arg!(--archive <FILE> "Stream the tiles into this .tar, .zip or .pmtiles file instead of writing single files")
+            .required(false),
+    )
+    .arg(
+        arg!(--"parallel-lods" <COUNT> "Number of LODs built at the same time")
+            .required(false)
+            .default_value("4"),
+    )
+    .arg(
+        arg!(--"encoder-threads" <COUNT> "Encode tiles on this many dedicated threads instead of the threads resizing them")
+            .required(false),
+    )
+    .arg(arg!(-y --yes "Overwrite tiles of a previous run without asking"))
+    .arg(arg!(--"keep-going" "Skip tiles which fail to be written and list them at the end"))
+    .arg(
+        arg!(--"failed-tiles" <FILE> "Like --keep-going, but also write the failed tiles as JSON to this file")
+            .required(false),
+    )
+    .arg(
+        arg!(--"only-tiles" <FILE> "Only build the tiles listed in this file, as written by --failed-tiles")
+            .required(false),
+    )
+}
+
+/// Sets up the writer of the tiles in `output_path` from the arguments of
+/// [`tile_writer_args`]. The `formats` are parsed by the command, as some
+/// only support certain ones.
+fn tile_writer_from_args(
+    args: &clap::ArgMatches,
+    output_path: &Path,
+    formats: TileFormats,
+) -> anyhow::Result<TileWriter> {
+    let tile_path = TilePathTemplate::new(args.value_of("tile-path").unwrap())?;
+    let mut writer = TileWriter::new(output_path, tile_path, formats)?;
+    if let Some(max) = parse_count(args, "max-concurrent-writes")? {
+        writer = writer.limit_concurrent_writes(max);
+    }
+    if let Some(threads) = parse_count(args, "encoder-threads")? {
+        writer = writer.encode_on_threads(threads);
+    }
+    if args.is_present("keep-going") || args.is_present("failed-tiles") {
+        writer = writer.keep_going();
+    }
+    if let Some(only_tiles_path) = args.value_of("only-tiles") {
+        writer = writer.only_tiles(TileStats::read_failures(Path::new(only_tiles_path))?);
+    }
+    if let Some(archive_path) = args.value_of("archive") {
+        writer = writer.archive(Path::new(archive_path))?;
+    }
+
+    Ok(writer)
+}
+
+/// [`confirm_overwrite`] for the tiles of `lods` about to be written by
+/// `writer`. Archives are written from scratch and tiles missing from a
+/// previous run (`--only-tiles`) are added to its tile set, so both skip it.
+fn confirm_tile_overwrite(
+    args: &clap::ArgMatches,
+    output_path: &Path,
+    writer: &TileWriter,
+    lods: RangeInclusive<u8>,
+) -> anyhow::Result<()> {
+    if args.is_present("archive") || args.is_present("only-tiles") {
+        return Ok(());
+    }
+
+    confirm_overwrite(
+        output_path,
+        writer.tile_path(),
+        lods,
+        args.is_present("yes"),
+    )
+}
+
+/// Checks the tile set in `output_path` for tiles of `lods` from a previous
+/// run and asks whether to overwrite (remove them), append to them or abort.
+/// Directories without a crs.json or tile.json aren't tile sets and are left
+/// alone. `--yes` overwrites without asking; without a terminal the tiles
+/// are kept and written over, like before.
+fn confirm_overwrite(
+    output_path: &Path,
+    tile_path: &TilePathTemplate,
+    lods: RangeInclusive<u8>,
+    yes: bool,
+) -> anyhow::Result<()> {
+    if !output_path.join("crs.json").is_file() && !output_path.join("tile.json").is_file() {
+        return Ok(());
+    }
+
+    let previous: BTreeSet<u8> = tile_path
+        .find_tiles(output_path)?
+        .iter()
+        .map(|((lod, _, _), _)| *lod)
+        .filter(|lod| lods.contains(lod))
+        .collect();
+    let (first, last) = match (previous.first(), previous.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Ok(()),
+    };
+
+    let interactive = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    if !yes && !interactive {
+        return Ok(());
+    }
+
+    if !yes {
+        warning!(
+            "{} already contains tiles of LOD {} to {}",
+            output_path.display(),
+            first,
+            last
+        );
+        match prompt("[o]verwrite, [a]ppend or a[b]ort? ")?.as_str() {
+            "o" | "overwrite" => {}
+            "a" | "append" => return Ok(()),
+            _ => bail!("Aborted"),
+        }
+    }
+
+    tile_path
+        .remove_tiles(output_path, |lod, _, _| lods.contains(&lod))
+        .with_context(|| format!("Failed to remove tiles from {}", output_path.display()))?;
+    info!("Removed tiles of previous run");
+
+    Ok(())
+}
+
+fn prompt(question: &str) -> anyhow::Result<String> {
+    print!("{}", question);
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+
+    Ok(answer.trim().to_lowercase())
+}
+
+/// Reads the watermark from `--watermark-text` or `--watermark-logo`.
+fn parse_watermark(args: &clap::ArgMatches) -> anyhow::Result<Option<Watermark>> {
+    match (
+        args.value_of("watermark-text"),
+        args.value_of("watermark-logo"),
+    ) {
+        (Some(_), Some(_)) => anyhow::bail!("Pass either --watermark-text or --watermark-logo"),
+        (Some(text), None) => Ok(Some(Watermark::Text(text.to_owned()))),
+        (None, Some(logo)) => Ok(Some(Watermark::logo(Path::new(logo))?)),
+        (None, None) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{confirm_overwrite, io_path, output_dir_in, parse_count};
+    use crate::utils::TilePathTemplate;
+    use clap::{arg, App};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    fn app() -> App<'static> {
+        App::new("test")
+            .arg(arg!(-i --input <INPUT_DIR> "").required(false))
+            .arg(arg!(-o --output <OUTPUT_DIR> "").required(false))
+            .arg(arg!([INPUT] "Same as --input"))
+            .arg(arg!([OUTPUT] "Same as --output"))
+    }
+
+    #[test]
+    fn flags_win_over_positional_args_and_env() {
+        let matches = app().get_matches_from(["test", "a", "b", "-i", "c"]);
+
+        assert_eq!(
+            PathBuf::from("c"),
+            io_path(&matches, "input", "INPUT", "MEH_TEST_UNSET").unwrap()
+        );
+        assert_eq!(
+            PathBuf::from("b"),
+            io_path(&matches, "output", "OUTPUT", "MEH_TEST_UNSET").unwrap()
+        );
+    }
+
+    #[test]
+    fn falls_back_to_env() {
+        let matches = app().get_matches_from(["test"]);
+        std::env::set_var("MEH_TEST_INPUT", "from-env");
+
+        assert_eq!(
+            PathBuf::from("from-env"),
+            io_path(&matches, "input", "INPUT", "MEH_TEST_INPUT").unwrap()
+        );
+        assert!(io_path(&matches, "output", "OUTPUT", "MEH_TEST_UNSET").is_err());
+    }
+
+    #[test]
+    fn creates_default_output_dir() {
+        let dir = TempDir::new("meh-utils-rust-output").unwrap();
+        let input_path = dir.path().join("input");
+        fs::create_dir(&input_path).unwrap();
+        fs::write(
+            input_path.join("meta.json"),
+            r#"{"author": "", "displayName": "Altis", "elevationOffset": 0, "gridOffsetX": 0,
+                "gridOffsetY": 0, "grids": [], "latitude": 0, "longitude": 0, "version": 0.1,
+                "worldName": "altis", "worldSize": 30720}"#,
+        )
+        .unwrap();
+        let matches = app().get_matches_from(["test"]);
+
+        let path = output_dir_in(&matches, &input_path, "sat", &dir.path().join("out")).unwrap();
+
+        assert_eq!(dir.path().join("out/altis/sat"), path);
+        assert!(path.is_dir());
+    }
+
+    #[test]
+    fn creates_given_output_dir() {
+        let dir = TempDir::new("meh-utils-rust-output").unwrap();
+        let output_path = dir.path().join("nested/output");
+        let matches = app().get_matches_from(["test", "-o", output_path.to_str().unwrap()]);
+
+        let path = output_dir_in(&matches, dir.path(), "sat", &dir.path().join("out")).unwrap();
+
+        assert_eq!(output_path, path);
+        assert!(output_path.is_dir());
+        assert!(!dir.path().join("out").exists());
+    }
+
+    #[test]
+    fn counts_have_to_be_positive() {
+        let app = App::new("test").arg(arg!(--count <COUNT> "").required(false));
+
+        let matches = app.clone().get_matches_from(["test", "--count", "3"]);
+        assert_eq!(Some(3), parse_count(&matches, "count").unwrap());
+        let matches = app.clone().get_matches_from(["test"]);
+        assert_eq!(None, parse_count(&matches, "count").unwrap());
+
+        for invalid in ["0", "1.5", "many"] {
+            let matches = app.clone().get_matches_from(["test", "--count", invalid]);
+            assert!(parse_count(&matches, "count").is_err());
+        }
+    }
+
+    #[test]
+    fn yes_removes_previous_lods() {
+        let dir = TempDir::new("meh-utils-rust-overwrite").unwrap();
+        for tile in ["0/0/0.png", "1/1/0.png", "2/3/1.png"] {
+            let path = dir.path().join(tile);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        fs::create_dir_all(dir.path().join("2024")).unwrap();
+        fs::write(dir.path().join("tile.json"), "{}").unwrap();
+
+        confirm_overwrite(dir.path(), &TilePathTemplate::default(), 0..=1, true).unwrap();
+
+        assert!(!dir.path().join("0").exists());
+        assert!(!dir.path().join("1").exists());
+        assert!(dir.path().join("2/3/1.png").exists());
+        assert!(dir.path().join("2024").exists());
+        assert!(dir.path().join("tile.json").exists());
+    }
+
+    #[test]
+    fn keeps_directories_which_are_no_tile_set() {
+        let dir = TempDir::new("meh-utils-rust-overwrite").unwrap();
+        fs::create_dir_all(dir.path().join("0/0")).unwrap();
+        fs::write(dir.path().join("0/0/0.png"), "").unwrap();
+
+        confirm_overwrite(dir.path(), &TilePathTemplate::default(), 0..=1, true).unwrap();
+
+        assert!(dir.path().join("0/0/0.png").exists());
+    }
+
+    #[test]
+    fn removes_tiles_of_custom_tile_paths() {
+        let dir = TempDir::new("meh-utils-rust-overwrite").unwrap();
+        let tile_path = TilePathTemplate::new("tiles/{z}-{x}-{y}.png").unwrap();
+        fs::create_dir_all(dir.path().join("tiles")).unwrap();
+        fs::write(dir.path().join("tiles/0-0-0.png"), "").unwrap();
+        fs::write(dir.path().join("tiles/legend.png"), "").unwrap();
+        fs::write(dir.path().join("crs.json"), "{}").unwrap();
+
+        confirm_overwrite(dir.path(), &tile_path, 0..=0, true).unwrap();
+
+        assert!(!dir.path().join("tiles/0-0-0.png").exists());
+        assert!(dir.path().join("tiles/legend.png").exists());
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::{bail, Context};
+use clap::{arg, App};
+use serde_json::json;
+
+use crate::commands::{input_path, output_path, Command};
+use crate::dem::{
+    find_mounts, load_map_dem, reconcile_world_size, relief, smooth, Mount, Smoothing,
+};
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use std::time::Instant;
+
+pub struct Mounts {}
+
+impl Command for Mounts {
+    fn register(&self) -> App<'static> {
+        App::new("mounts")
+            .about("Find the peaks of the DEM and write them with their elevation and prominence as GeoJSON or CSV.")
+            .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory (or MEH_INPUT)").required(false))
+            .arg(arg!(-o --output <OUTPUT_FILE> "Path to output file (.geojson or .csv) (or MEH_OUTPUT)").required(false))
+            .arg(arg!([INPUT] "Same as --input"))
+            .arg(arg!([OUTPUT] "Same as --output"))
+            .arg(
+                arg!(--"min-prominence" <METERS> "Minimum height of a peak above the highest saddle to higher terrain")
+                    .required(false)
+                    .default_value("50"),
+            )
+            .arg(
+                arg!(--"neighbor-radius" <CELLS> "A peak has to be higher than all cells within this radius")
+                    .required(false)
+                    .default_value("1"),
+            )
+            .arg(
+                arg!(--"min-elevation" <METERS> "Minimum elevation (including elevationOffset) of a peak")
+                    .required(false),
+            )
+            .arg(
+                arg!(--"min-relief" <METERS> "Minimum height of a peak above the mean elevation around it")
+                    .required(false),
+            )
+            .arg(
+                arg!(--"relief-radius" <CELLS> "Radius of the area around a peak for --min-relief")
+                    .required(false)
+                    .default_value("5"),
+            )
+            .arg(
+                arg!(--smooth <FILTER> "Smooth the DEM before finding peaks, which suppresses noise like boulders")
+                    .required(false)
+                    .possible_values(["gaussian", "median"]),
+            )
+            .arg(
+                arg!(--"smooth-radius" <CELLS> "Radius of the smoothing filter")
+                    .required(false)
+                    .default_value("1"),
+            )
+            .arg(arg!(--"no-dem-cache" "Always parse the DEM instead of using/writing dem.cache"))
+            .arg(arg!(--"rescale-dem" "Scale the DEM to span the worldSize of meta.json"))
+    }
+    fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
+        let start = Instant::now();
+
+        let input_path = &input_path(args)?;
+        let output_path = &output_path(args)?;
+
+        let min_prominence = args
+            .value_of("min-prominence")
+            .unwrap()
+            .parse::<f32>()
+            .context("Invalid minimum prominence")?;
+        let radius = args
+            .value_of("neighbor-radius")
+            .unwrap()
+            .parse::<usize>()
+            .context("Invalid neighbor radius")?;
+        if radius == 0 {
+            bail!("Neighbor radius has to be at least one cell");
+        }
+        let min_elevation = args
+            .value_of("min-elevation")
+            .map(|value| value.parse::<f32>())
+            .transpose()
+            .context("Invalid minimum elevation")?;
+        let min_relief = args
+            .value_of("min-relief")
+            .map(|value| value.parse::<f32>())
+            .transpose()
+            .context("Invalid minimum relief")?;
+        let relief_radius = args
+            .value_of("relief-radius")
+            .unwrap()
+            .parse::<usize>()
+            .context("Invalid relief radius")?;
+        let smoothing = match args.value_of("smooth") {
+            Some("gaussian") => Some(Smoothing::Gaussian),
+            Some("median") => Some(Smoothing::Median),
+            _ => None,
+        };
+        let smooth_radius = args
+            .value_of("smooth-radius")
+            .unwrap()
+            .parse::<usize>()
+            .context("Invalid smoothing radius")?;
+
+        step!("Loading meta.json");
+        let meta = crate::metajson::from_file(&input_path.join("meta.json"))?;
+        done!("Loaded meta.json");
+
+        let now = Instant::now();
+        step!("Loading DEM");
+        let mut dem = load_map_dem(input_path, !args.is_present("no-dem-cache"))?;
+        done!("Loaded DEM in {}ms", now.elapsed().as_millis());
+        reconcile_world_size(&mut dem, meta.world_size, args.is_present("rescale-dem"));
+
+        let smoothed = match smoothing {
+            Some(smoothing) => {
+                let now = Instant::now();
+                step!("Smoothing DEM");
+                let smoothed = smooth(&dem, smoothing, smooth_radius);
+                done!("Smoothed DEM in {}ms", now.elapsed().as_millis());
+                Some(smoothed)
+            }
+            None => None,
+        };
+
+        let now = Instant::now();
+        step!("Finding mounts");
+        // in-game coordinates start at the lower left corner of the DEM (which is
+        // usually at an easting of 200000)
+        let (left, bottom) = dem.lower_left();
+        let searched = smoothed.as_ref().unwrap_or(&dem);
+        let mounts: Vec<Mount> = find_mounts(searched, min_prominence, radius)
+            .into_iter()
+            .map(|mount| Mount {
+                // label peaks with their real elevation, even if found smoothed
+                elevation: dem.sample(mount.x, mount.y).unwrap_or(mount.elevation),
+                ..mount
+            })
+            .filter(|mount| match min_relief {
+                Some(min_relief) => relief(&dem, mount, relief_radius) >= min_relief,
+                None => true,
+            })
+            .map(|mount| Mount {
+                x: mount.x - left,
+                y: mount.y - bottom,
+                elevation: mount.elevation + meta.elevation_offset,
+                ..mount
+            })
+            .filter(|mount| match min_elevation {
+                Some(min_elevation) => mount.elevation >= min_elevation,
+                None => true,
+            })
+            .collect();
+        write_mounts(output_path, &mounts)?;
+        done!(
+            "Wrote {} mounts in {}ms",
+            mounts.len(),
+            now.elapsed().as_millis()
+        );
+
+        crate::output::finished(start);
+
+        Ok(())
+    }
+}
+
+fn write_mounts(path: &Path, mounts: &[Mount]) -> anyhow::Result<()> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match extension.as_deref() {
+        Some("geojson") | Some("json") => {
+            let features: Vec<_> = mounts
+                .iter()
+                .map(|mount| {
+                    json!({
+                        "type": "Feature",
+                        "properties": {
+                            "elevation": mount.elevation,
+                            "text": format!("{:.0}", mount.elevation),
+                            "prominence": mount.prominence,
+                        },
+                        "geometry": {
+                            "type": "Point",
+                            "coordinates": [mount.x, mount.y],
+                        },
+                    })
+                })
+                .collect();
+            let geojson = json!({ "type": "FeatureCollection", "features": features });
+            serde_json::to_writer(&mut writer, &geojson)?;
+        }
+        Some("csv") => {
+            writeln!(writer, "x,y,elevation,prominence")?;
+            for m in mounts {
+                writeln!(writer, "{},{},{},{}", m.x, m.y, m.elevation, m.prominence)?;
+            }
+        }
+        _ => bail!("Output file has to end with .geojson or .csv"),
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
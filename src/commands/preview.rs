@@ -1,17 +1,299 @@
-use anyhow::bail;
+use anyhow::{bail, Context};
 use clap::{arg, App};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
-use crate::commands::Command;
-use crate::utils::encode_png;
+use crate::commands::{input_path, output_dir, parse_watermark, Command};
+use crate::crs::CRS;
+use crate::utils::{
+    build_tile_set, calc_max_lod, encode_png, pad_to_tile_grid, parse_formats, TilePathTemplate,
+    TileWriter, Watermark,
+};
 
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::{overlay, FilterType};
 use image::io::Reader as ImageReader;
+use image::{ColorType, DynamicImage, Rgba, RgbaImage};
+use std::fs::File;
+use std::io::BufWriter;
 use std::path::Path;
 
 use std::time::Instant;
 
+const FAVICON_SIZES: [u32; 3] = [16, 32, 48];
+const SOCIAL_SIZES: [(u32, u32); 2] = [(1200, 630), (1280, 720)];
+/// used for letterboxing if meta.json has no colorOutside
+const DEFAULT_COLOR_OUTSIDE: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
 pub struct Preview {}
 
+#[derive(Debug, Default)]
+pub struct PreviewOptions {
+    /// also build favicon.ico, apple-touch-icon.png and banner.png
+    pub web_assets: bool,
+    /// also build letterboxed link preview images
+    pub social: bool,
+    /// stamped on all preview images except for the icons
+    pub watermark: Option<Watermark>,
+}
+
+impl Command for Preview {
+    fn register(&self) -> App<'static> {
+        App::new("preview")
+            .about("Build resolutions for preview image.")
+            .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory (or MEH_INPUT)").required(false))
+            .arg(arg!(-o --output <OUTPUT_DIR> "Path to output directory (or MEH_OUTPUT, default: out/<worldName>/preview)").required(false))
+            .arg(arg!([INPUT] "Same as --input"))
+            .arg(arg!([OUTPUT] "Same as --output"))
+            .arg(arg!(--"world-file" "Also write world files for preview.png (needs meta.json)"))
+            .arg(arg!(--"aux-xml" "Also write an .aux.xml with the coordinate system next to the world files"))
+            .arg(arg!(--"web-assets" "Also build favicon.ico, a 180x180 apple-touch-icon.png and a 1200x630 banner.png"))
+            .arg(arg!(--"watermark-text" <TEXT> "Attribution stamped on the preview images").required(false))
+            .arg(arg!(--"watermark-logo" <PNG> "Logo stamped on the preview images").required(false))
+            .arg(arg!(--manifest <MAP_JSON> "Add the preview images to this map.json, which is created if missing (needs meta.json)").required(false))
+            .arg(arg!(--social "Also build 1200x630 and 1280x720 link preview images, letterboxed in the colorOutside of meta.json"))
+            .arg(arg!(--tiles <LODS> "Also build a tile set with this many LODs from preview.png in tiles/ (needs meta.json)").required(false))
+    }
+    fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
+
+        let input_path = &input_path(args)?;
+        let output_path = &output_dir(args, input_path, "preview")?;
+
+        let options = PreviewOptions {
+            web_assets: args.is_present("web-assets"),
+            social: args.is_present("social"),
+            watermark: parse_watermark(args)?,
+        };
+
+        self.exec(input_path, output_path, &options)?;
+
+        if args.is_present("world-file") {
+            self.write_world_file(input_path, output_path, args.is_present("aux-xml"))?;
+        }
+
+        let tiles = args
+            .value_of("tiles")
+            .map(|v| {
+                v.parse::<u8>()
+                    .context("Invalid number of preview tile LODs")
+            })
+            .transpose()?;
+        if let Some(lods) = tiles {
+            self.write_tiles(input_path, output_path, lods)?;
+        }
+
+        if let Some(manifest_path) = args.value_of("manifest") {
+            let meta = crate::metajson::from_file(&input_path.join("meta.json"))?;
+            crate::manifest::add_preview(Path::new(manifest_path), &meta, output_path)?;
+            if tiles.is_some() {
+                let tiles_path = output_path.join("tiles");
+                crate::manifest::add_tile_set(
+                    Path::new(manifest_path),
+                    &meta,
+                    "preview",
+                    &tiles_path,
+                )?;
+            }
+            done!("Added preview images to {}", manifest_path);
+        }
+
+        Ok(())
+    }
+}
+impl Preview {
+    fn exec(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        options: &PreviewOptions,
+    ) -> anyhow::Result<()> {
+        let start = Instant::now();
+
+        if !output_path.is_dir() {
+            bail!("Output path is not a directory");
+        }
+
+        let preview_path = input_path.join("preview.png");
+        if !preview_path.is_file() {
+            bail!("Couldn't find preview.png");
+        }
+
+        let now = Instant::now();
+        step!("Loading preview image");
+        let img = ImageReader::open(preview_path)?.decode()?;
+        done!("Loaded preview image in {}ms", now.elapsed().as_millis());
+
+        let stamp = |img: DynamicImage| match &options.watermark {
+            Some(watermark) => watermark.apply(&img),
+            None => img,
+        };
+
+        let now = Instant::now();
+        step!("Writing original preview image to output");
+        if let Err(e) = encode_png(&output_path.join("preview.png"), &stamp(img.clone())) {
+            error!("Failed to write original preview image: {}", e);
+        } else {
+            done!(
+                "Wrote original preview image in {}ms",
+                now.elapsed().as_millis()
+            );
+        }
+
+        [128u32, 256, 512, 1024].par_iter().for_each(|size| {
+            let now = Instant::now();
+            step!("Building x{} image", size);
+
+            let thumb = stamp(img.thumbnail(*size, *size));
+            let thumb_path = output_path.join(format!("preview_{}.png", size));
+
+            if let Err(e) = encode_png(&thumb_path, &thumb) {
+                error!("Build of x{} failed: {}", size, e);
+            } else {
+                done!("Built x{} in {}ms", size, now.elapsed().as_millis())
+            }
+        });
+
+        if options.web_assets {
+            let now = Instant::now();
+            step!("Building web assets");
+            if let Err(e) = write_web_assets(&img, output_path, &stamp) {
+                error!("Build of web assets failed: {}", e);
+            } else {
+                done!("Built web assets in {}ms", now.elapsed().as_millis())
+            }
+        }
+
+        if options.social {
+            let now = Instant::now();
+            step!("Building link preview images");
+            let meta = crate::metajson::from_file(&input_path.join("meta.json"))?;
+            let color = meta.color_outside_rgba().unwrap_or(DEFAULT_COLOR_OUTSIDE);
+            if let Err(e) = write_social_images(&img, color, output_path, &stamp) {
+                error!("Build of link preview images failed: {}", e);
+            } else {
+                done!(
+                    "Built link preview images in {}ms",
+                    now.elapsed().as_millis()
+                )
+            }
+        }
+
+        crate::output::finished(start);
+
+        Ok(())
+    }
+
+    fn write_world_file(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        aux_xml: bool,
+    ) -> anyhow::Result<()> {
+        let meta = crate::metajson::from_file(&input_path.join("meta.json"))?;
+
+        let preview_path = output_path.join("preview.png");
+        let (width, height) = image::image_dimensions(&preview_path)?;
+
+        crate::worldfile::write(&preview_path, width, height, meta.world_size, aux_xml)?;
+        done!("Wrote world file for preview image");
+
+        Ok(())
+    }
+
+    /// Builds a small tile set of the first `lods` LODs from preview.png, e.g.
+    /// for a zoomable minimap which doesn't need the full satellite tiles.
+    fn write_tiles(&self, input_path: &Path, output_path: &Path, lods: u8) -> anyhow::Result<()> {
+        if lods == 0 {
+            bail!("Preview tiles need at least one LOD");
+        }
+
+        let now = Instant::now();
+        step!("Building preview tiles");
+        let meta = crate::metajson::from_file(&input_path.join("meta.json"))?;
+        let img = ImageReader::open(input_path.join("preview.png"))?.decode()?;
+
+        // tiles in in-game meters need a square source with a power of two size
+        let width = img.width();
+        let img = pad_to_tile_grid(img, meta.color_outside_rgba().unwrap_or(Rgba([0, 0, 0, 0])));
+        let tile_extent = meta.world_size as f64 * img.width() as f64 / width as f64;
+
+        let max_lod = (lods - 1).min(calc_max_lod(&img));
+        if max_lod < lods - 1 {
+            warning!("preview.png is only big enough for {} LODs", max_lod + 1);
+        }
+
+        let tiles_path = output_path.join("tiles");
+        std::fs::create_dir_all(&tiles_path)?;
+        let writer = TileWriter::new(
+            &tiles_path,
+            TilePathTemplate::default(),
+            parse_formats("png")?,
+        )?;
+        for lod in 0..=max_lod {
+            build_tile_set(&writer, &img, lod)?;
+        }
+
+        let crs = CRS::new(&meta, None).with_tile_extent(tile_extent);
+        crate::crs::write(&tiles_path, &crs)?;
+        crate::tilejson::write(&tiles_path, 0, max_lod, &meta, crs, "Preview", Vec::new())?;
+        done!("Built preview tiles in {}ms", now.elapsed().as_millis());
+
+        Ok(())
+    }
+}
+
+fn write_web_assets(
+    img: &DynamicImage,
+    output_path: &Path,
+    stamp: &(dyn Fn(DynamicImage) -> DynamicImage + Sync),
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let frames = FAVICON_SIZES
+        .iter()
+        .map(|size| {
+            let icon = img.resize_to_fill(*size, *size, FilterType::Lanczos3).to_rgba8();
+            IcoFrame::as_png(&icon, *size, *size, ColorType::Rgba8)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let favicon = BufWriter::new(File::create(output_path.join("favicon.ico"))?);
+    IcoEncoder::new(favicon).encode_images(&frames)?;
+
+    let touch_icon = img.resize_to_fill(180, 180, FilterType::Lanczos3);
+    encode_png(&output_path.join("apple-touch-icon.png"), &touch_icon)?;
+
+    let banner = stamp(img.resize_to_fill(1200, 630, FilterType::Lanczos3));
+    encode_png(&output_path.join("banner.png"), &banner)?;
+
+    Ok(())
+}
+
+fn write_social_images(
+    img: &DynamicImage,
+    background: Rgba<u8>,
+    output_path: &Path,
+    stamp: &(dyn Fn(DynamicImage) -> DynamicImage + Sync),
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    for (width, height) in SOCIAL_SIZES {
+        let social = stamp(letterbox(img, width, height, background));
+        encode_png(
+            &output_path.join(format!("social_{}x{}.png", width, height)),
+            &social,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Scales the image to fit into `width`x`height` and centers it on a canvas of that size.
+fn letterbox(img: &DynamicImage, width: u32, height: u32, background: Rgba<u8>) -> DynamicImage {
+    let fitted = img.resize(width, height, FilterType::Lanczos3);
+    let mut canvas = RgbaImage::from_pixel(width, height, background);
+
+    let x = (width - fitted.width()) / 2;
+    let y = (height - fitted.height()) / 2;
+    overlay(&mut canvas, &fitted.to_rgba8(), x as i64, y as i64);
+
+    DynamicImage::ImageRgba8(canvas)
+}
+
 #[cfg(test)]
 #[allow(unused_must_use)]
 mod tests {
@@ -21,6 +303,8 @@ mod tests {
     use std::path::{Path, PathBuf};
     use crate::Command;
     use crate::commands::Preview;
+    use crate::commands::preview::{letterbox, PreviewOptions, FAVICON_SIZES};
+    use image::{DynamicImage, Rgba, RgbaImage};
     use tempdir::TempDir;
 
     fn with_input_and_output_paths(f: fn(PathBuf, PathBuf) -> ()) -> std::io::Result<()> {
@@ -45,15 +329,15 @@ mod tests {
     fn exec_bails_if_input_or_output_dirs_do_not_exist() {
 
         with_input_and_output_paths(|input_path, output_path| {
-            assert!((Preview {}).exec(&input_path, &Path::new("yolo")).is_err());
-            assert!((Preview {}).exec(&Path::new("yolo"), &output_path).is_err());
+            assert!((Preview {}).exec(&input_path, Path::new("yolo"), &PreviewOptions::default()).is_err());
+            assert!((Preview {}).exec(Path::new("yolo"), &output_path, &PreviewOptions::default()).is_err());
         });
     }
 
     #[test]
     fn exec_bails_if_input_preview_file_does_not_exist() {
         with_input_and_output_paths(|input_path, output_path| {
-            assert!((Preview {}).exec(&input_path, &output_path).is_err());
+            assert!((Preview {}).exec(&input_path, &output_path, &PreviewOptions::default()).is_err());
         });
     }
 
@@ -62,7 +346,7 @@ mod tests {
         with_input_and_output_paths(|input_path, output_path| {
             let mut preview_png = File::create(input_path.join(Path::new("preview.png"))).unwrap();
             assert!(preview_png.write("foo".as_bytes()).is_ok());
-            assert!((Preview {}).exec(&input_path, &output_path).is_err());
+            assert!((Preview {}).exec(&input_path, &output_path, &PreviewOptions::default()).is_err());
         });
     }
 
@@ -71,7 +355,7 @@ mod tests {
         with_input_and_output_paths(|input_path, output_path| {
             assert!(fs::copy(Path::new("./resources/test/happy/input/preview.png"), input_path.join("preview.png")).is_ok());
 
-            assert!((Preview {}).exec(&input_path, &output_path).is_ok());
+            assert!((Preview {}).exec(&input_path, &output_path, &PreviewOptions::default()).is_ok());
 
 
             let mut preview_files: Vec<String> = output_path
@@ -82,7 +366,7 @@ mod tests {
                 .collect();
 
             fn to_num(e: &str) -> i32 {
-                let digits: String = e.chars().filter(|c| { c.is_digit(10) }).collect();
+                let digits: String = e.chars().filter(|c| { c.is_ascii_digit() }).collect();
                 digits.parse::<i32>().unwrap()
             }
 
@@ -97,73 +381,39 @@ mod tests {
             assert_eq!("preview_1024.png", preview_files[3]);
         });
     }
-}
-
-impl Command for Preview {
-    fn register(&self) -> App<'static> {
-        App::new("preview")
-            .about("Build resolutions for preview image.")
-            .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory"))
-            .arg(arg!(-o --output <OUTPUT_DIR> "Path to output directory"))
-    }
-    fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
-
-        let input_path_str = args.value_of("input").unwrap();
-        let output_path_str = args.value_of("output").unwrap();
 
-        let input_path = Path::new(input_path_str);
-        let output_path = Path::new(output_path_str);
-
-        self.exec(input_path, output_path)
+    #[test]
+    fn exec_writes_web_assets() {
+        with_input_and_output_paths(|input_path, output_path| {
+            assert!(fs::copy(Path::new("./resources/test/happy/input/preview.png"), input_path.join("preview.png")).is_ok());
+            let options = PreviewOptions {
+                web_assets: true,
+                ..PreviewOptions::default()
+            };
+
+            assert!((Preview {}).exec(&input_path, &output_path, &options).is_ok());
+
+            let dimensions = |name: &str| image::image_dimensions(output_path.join(name)).unwrap();
+            assert_eq!((180, 180), dimensions("apple-touch-icon.png"));
+            assert_eq!((1200, 630), dimensions("banner.png"));
+
+            let favicon = image::io::Reader::open(output_path.join("favicon.ico")).unwrap();
+            assert_eq!(Some(image::ImageFormat::Ico), favicon.format());
+            // the largest icon is decoded
+            let largest = *FAVICON_SIZES.iter().max().unwrap();
+            assert_eq!((largest, largest), favicon.into_dimensions().unwrap());
+        });
     }
-}
-impl Preview {
-    fn exec(&self, input_path: &Path, output_path: &Path) -> anyhow::Result<()> {
-        let start = Instant::now();
-
-        if !output_path.is_dir() {
-            bail!("Output path is not a directory");
-        }
-
-        let preview_path = input_path.join("preview.png");
-        if !preview_path.is_file() {
-            bail!("Couldn't find preview.png");
-        }
-
-        let now = Instant::now();
-        println!("▶️  Loading preview image");
-        let img = ImageReader::open(preview_path)?.decode()?;
-        println!("✔️  Loaded preview image in {}ms", now.elapsed().as_millis());
 
-        let now = Instant::now();
-        println!("▶️  Writing original preview image to output");
-        if let Err(e) = encode_png(&output_path.join("preview.png"), &img) {
-            println!("❌  Failed to write original preview image");
-            println!("{}", e);
-        } else {
-            println!(
-                "✔️  Wrote original preview image in {}ms",
-                now.elapsed().as_millis()
-            );
-        }
-
-        [128u32, 256, 512, 1024].par_iter().for_each(|size| {
-            let now = Instant::now();
-            println!("▶️  Building x{} image", size);
-
-            let thumb = img.thumbnail(*size, *size);
-            let thumb_path = output_path.join(format!("preview_{}.png", size));
-
-            if let Err(e) = encode_png(&thumb_path, &thumb) {
-                println!("❌  Build of x{} failed", size);
-                println!("{}", e);
-            } else {
-                println!("✔️  Built x{} in {}ms", size, now.elapsed().as_millis())
-            }
-        });
+    #[test]
+    fn letterbox_centers_image_on_background() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(10, 10, Rgba([255, 0, 0, 255])));
 
-        println!("\n    🎉  Finished in {}ms", start.elapsed().as_millis());
+        let boxed = letterbox(&img, 40, 20, Rgba([0, 0, 255, 255])).to_rgba8();
 
-        Ok(())
+        assert_eq!((40, 20), boxed.dimensions());
+        assert_eq!(Rgba([0, 0, 255, 255]), *boxed.get_pixel(0, 10));
+        assert_eq!(Rgba([255, 0, 0, 255]), *boxed.get_pixel(20, 10));
+        assert_eq!(Rgba([0, 0, 255, 255]), *boxed.get_pixel(39, 10));
     }
 }
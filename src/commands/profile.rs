@@ -0,0 +1,304 @@
+use anyhow::{bail, Context};
+use clap::{arg, App};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::commands::{input_path, output_path, Command};
+use crate::dem::{load_map_dem, reconcile_world_size, DEMRaster};
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use std::time::Instant;
+
+pub struct Profile {}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct ProfilePoint {
+    /// distance along the line in meters
+    distance: f64,
+    x: f64,
+    y: f64,
+    /// `None` outside of the DEM or on nodata cells
+    elevation: Option<f32>,
+}
+
+impl Command for Profile {
+    fn register(&self) -> App<'static> {
+        App::new("profile")
+            .about("Sample the DEM along a line and write the elevation profile as JSON or CSV.")
+            .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory (or MEH_INPUT)").required(false))
+            .arg(arg!(-o --output <OUTPUT_FILE> "Path to output file (.json or .csv) (or MEH_OUTPUT)").required(false))
+            .arg(arg!([INPUT] "Same as --input"))
+            .arg(arg!([OUTPUT] "Same as --output"))
+            .arg(
+                arg!(--line <COORDS> "Line as X,Y pairs in in-game meters, separated by spaces or semicolons")
+                    .required(false),
+            )
+            .arg(
+                arg!(--geojson <FILE> "GeoJSON file with a LineString (in in-game meters)")
+                    .required(false),
+            )
+            .arg(
+                arg!(--interval <METERS> "Distance between samples in meters")
+                    .required(false)
+                    .default_value("10"),
+            )
+            .arg(arg!(--"no-dem-cache" "Always parse the DEM instead of using/writing dem.cache"))
+            .arg(arg!(--"rescale-dem" "Scale the DEM to span the worldSize of meta.json"))
+    }
+    fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
+        let start = Instant::now();
+
+        let input_path = &input_path(args)?;
+        let output_path = &output_path(args)?;
+        let format = ProfileFormat::from_path(output_path)?;
+
+        let line = match (args.value_of("line"), args.value_of("geojson")) {
+            (Some(coords), None) => parse_line(coords)?,
+            (None, Some(geojson)) => read_geojson_line(Path::new(geojson))?,
+            _ => bail!("Pass either --line or --geojson"),
+        };
+
+        let interval = args
+            .value_of("interval")
+            .unwrap()
+            .parse::<f64>()
+            .context("Invalid interval")?;
+        if interval <= 0.0 {
+            bail!("Interval has to be greater than zero");
+        }
+
+        step!("Loading meta.json");
+        let meta = crate::metajson::from_file(&input_path.join("meta.json"))?;
+        done!("Loaded meta.json");
+
+        let now = Instant::now();
+        step!("Loading DEM");
+        let mut dem = load_map_dem(input_path, !args.is_present("no-dem-cache"))?;
+        done!("Loaded DEM in {}ms", now.elapsed().as_millis());
+        reconcile_world_size(&mut dem, meta.world_size, args.is_present("rescale-dem"));
+
+        let now = Instant::now();
+        step!("Sampling elevation profile");
+        let profile = sample_profile(&dem, &line, interval, meta.elevation_offset);
+        write_profile(output_path, format, &profile)?;
+        done!(
+            "Wrote {} samples in {}ms",
+            profile.len(),
+            now.elapsed().as_millis()
+        );
+
+        crate::output::finished(start);
+
+        Ok(())
+    }
+}
+
+/// Samples the DEM every `interval` meters along the line. The vertices of
+/// the line are always part of the profile.
+fn sample_profile(
+    dem: &DEMRaster,
+    line: &[(f64, f64)],
+    interval: f64,
+    elevation_offset: f32,
+) -> Vec<ProfilePoint> {
+    // in-game coordinates start at the lower left corner of the DEM (which is
+    // usually at an easting of 200000)
+    let (left, bottom) = dem.lower_left();
+    let point = |distance: f64, x: f64, y: f64| ProfilePoint {
+        distance,
+        x,
+        y,
+        elevation: dem
+            .sample(left + x as f32, bottom + y as f32)
+            .map(|z| z + elevation_offset),
+    };
+
+    let mut profile = Vec::new();
+    let mut distance = 0.0;
+
+    if let Some(&(x, y)) = line.first() {
+        profile.push(point(0.0, x, y));
+    }
+
+    for segment in line.windows(2) {
+        let (x1, y1) = segment[0];
+        let (x2, y2) = segment[1];
+        let length = (x2 - x1).hypot(y2 - y1);
+
+        let mut offset = interval;
+        while offset < length {
+            let t = offset / length;
+            profile.push(point(
+                distance + offset,
+                x1 + (x2 - x1) * t,
+                y1 + (y2 - y1) * t,
+            ));
+            offset += interval;
+        }
+
+        distance += length;
+        profile.push(point(distance, x2, y2));
+    }
+
+    profile
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ProfileFormat {
+    Json,
+    Csv,
+}
+
+impl ProfileFormat {
+    /// Format of the output file, picked from its extension.
+    fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+
+        match extension.as_deref() {
+            Some("json") => Ok(ProfileFormat::Json),
+            Some("csv") => Ok(ProfileFormat::Csv),
+            _ => bail!("Output file has to end with .json or .csv"),
+        }
+    }
+}
+
+fn write_profile(
+    path: &Path,
+    format: ProfileFormat,
+    profile: &[ProfilePoint],
+) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match format {
+        ProfileFormat::Json => serde_json::to_writer(&mut writer, profile)?,
+        ProfileFormat::Csv => {
+            writeln!(writer, "distance,x,y,elevation")?;
+            for p in profile {
+                let elevation = p.elevation.map(|z| z.to_string()).unwrap_or_default();
+                writeln!(writer, "{},{},{},{}", p.distance, p.x, p.y, elevation)?;
+            }
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Parses lines like `100,200 300,400` or `100,200;300,400`.
+fn parse_line(value: &str) -> anyhow::Result<Vec<(f64, f64)>> {
+    let line = value
+        .split(|c: char| c == ';' || c.is_whitespace())
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once(',') {
+            Some((x, y)) => Ok((x.trim().parse::<f64>()?, y.trim().parse::<f64>()?)),
+            None => bail!("Expected X,Y but got '{}'", pair),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .context("Invalid line")?;
+
+    if line.len() < 2 {
+        bail!("A line needs at least two points");
+    }
+
+    Ok(line)
+}
+
+/// Reads the first LineString of a GeoJSON geometry, feature or feature collection.
+fn read_geojson_line(path: &Path) -> anyhow::Result<Vec<(f64, f64)>> {
+    let file = File::open(path).with_context(|| format!("Couldn't open {}", path.display()))?;
+    let geojson: Value = serde_json::from_reader(BufReader::new(file))?;
+
+    match find_line_string(&geojson) {
+        Some(line) if line.len() >= 2 => Ok(line),
+        _ => bail!("{} contains no LineString", path.display()),
+    }
+}
+
+fn find_line_string(geojson: &Value) -> Option<Vec<(f64, f64)>> {
+    match geojson.get("type")?.as_str()? {
+        "LineString" => geojson
+            .get("coordinates")?
+            .as_array()?
+            .iter()
+            .map(|position| Some((position.get(0)?.as_f64()?, position.get(1)?.as_f64()?)))
+            .collect(),
+        "Feature" => find_line_string(geojson.get("geometry")?),
+        "FeatureCollection" => geojson
+            .get("features")?
+            .as_array()?
+            .iter()
+            .find_map(find_line_string),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_line_string, parse_line, sample_profile, ProfileFormat};
+    use crate::dem::{raster::Origin, DEMRaster};
+    use std::path::Path;
+
+    #[test]
+    fn parses_lines() {
+        assert_eq!(
+            vec![(0.0, 0.0), (10.5, 20.0), (30.0, 40.0)],
+            parse_line("0,0 10.5,20;30,40").unwrap()
+        );
+        assert!(parse_line("0,0").is_err());
+        assert!(parse_line("0,0 10").is_err());
+    }
+
+    #[test]
+    fn finds_line_strings() {
+        let geojson = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [
+                { "type": "Feature", "geometry": { "type": "Point", "coordinates": [1, 2] } },
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "LineString", "coordinates": [[1, 2], [3.5, 4]] }
+                }
+            ]
+        });
+
+        assert_eq!(
+            Some(vec![(1.0, 2.0), (3.5, 4.0)]),
+            find_line_string(&geojson)
+        );
+    }
+
+    #[test]
+    fn samples_at_interval_and_vertices() {
+        // slope rising by one meter per meter towards the east
+        let data = (0..9).map(|i| (i % 3) as f32).collect();
+        let dem = DEMRaster::new(3, 3, Origin::Corner(0.0, 0.0), 1.0, -9999.0, data);
+
+        let profile = sample_profile(&dem, &[(0.0, 2.0), (2.0, 2.0), (2.0, 1.0)], 0.75, 10.0);
+
+        let distances: Vec<_> = profile.iter().map(|p| p.distance).collect();
+        assert_eq!(vec![0.0, 0.75, 1.5, 2.0, 2.75, 3.0], distances);
+        assert_eq!(Some(10.75), profile[1].elevation);
+        assert_eq!(Some(12.0), profile[5].elevation);
+    }
+
+    #[test]
+    fn picks_format_from_extension() {
+        assert_eq!(
+            ProfileFormat::Json,
+            ProfileFormat::from_path(Path::new("profile.JSON")).unwrap()
+        );
+        assert_eq!(
+            ProfileFormat::Csv,
+            ProfileFormat::from_path(Path::new("profile.csv")).unwrap()
+        );
+        assert!(ProfileFormat::from_path(Path::new("profile.txt")).is_err());
+        assert!(ProfileFormat::from_path(Path::new("profile")).is_err());
+    }
+}
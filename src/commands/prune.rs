@@ -0,0 +1,258 @@
+use anyhow::{bail, Context};
+use clap::{arg, App};
+
+use crate::commands::{output_path, Command};
+use crate::crs::CRS;
+use crate::utils::{TilePathTemplate, DEFAULT_TILE_PATH};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use std::time::Instant;
+
+pub struct Prune {}
+
+impl Command for Prune {
+    fn register(&self) -> App<'static> {
+        App::new("prune")
+            .about("Remove tiles outside of a LOD range or inside of a bounding box from a tile set.")
+            .arg(arg!(-o --output <OUTPUT_DIR> "Path to the tile set directory to prune (or MEH_OUTPUT)").required(false))
+            .arg(arg!([OUTPUT] "Same as --output"))
+            .arg(
+                arg!(--"tile-path" <TEMPLATE> "Layout of the tiles within the tile set, as passed when building it")
+                    .required(false)
+                    .default_value(DEFAULT_TILE_PATH),
+            )
+            .arg(arg!(--"min-lod" <LOD> "Remove all LODs below this one").required(false))
+            .arg(arg!(--"max-lod" <LOD> "Remove all LODs above this one").required(false))
+            .arg(
+                arg!(--bbox <BBOX> "Remove tiles which lie completely within MIN_X,MIN_Y,MAX_X,MAX_Y (in-game meters)")
+                    .required(false),
+            )
+    }
+    fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
+        let output_path = &output_path(args)?;
+
+        let min_lod = args
+            .value_of("min-lod")
+            .map(|v| v.parse::<u8>().context("Invalid min LOD"))
+            .transpose()?;
+        let max_lod = args
+            .value_of("max-lod")
+            .map(|v| v.parse::<u8>().context("Invalid max LOD"))
+            .transpose()?;
+        let bbox = args.value_of("bbox").map(parse_bbox).transpose()?;
+        let tile_path = TilePathTemplate::new(args.value_of("tile-path").unwrap())?;
+
+        if min_lod.is_none() && max_lod.is_none() && bbox.is_none() {
+            bail!("Nothing to prune, pass --min-lod, --max-lod and/or --bbox");
+        }
+
+        self.exec(output_path, &tile_path, min_lod, max_lod, bbox)
+    }
+}
+
+impl Prune {
+    fn exec(
+        &self,
+        output_path: &Path,
+        tile_path: &TilePathTemplate,
+        min_lod: Option<u8>,
+        max_lod: Option<u8>,
+        bbox: Option<[f64; 4]>,
+    ) -> anyhow::Result<()> {
+        let start = Instant::now();
+
+        if !output_path.is_dir() {
+            bail!("Output path is not a directory");
+        }
+
+        let crs = match bbox {
+            Some(_) => Some(
+                crate::crs::from_file(&output_path.join("crs.json"))
+                    .context("Pruning by bounding box needs the crs.json of the tile set")?,
+            ),
+            None => None,
+        };
+
+        let removed = tile_path.remove_tiles(output_path, |lod, _, _| {
+            min_lod.is_some_and(|min| lod < min) || max_lod.is_some_and(|max| lod > max)
+        })?;
+        let removed_lods: BTreeSet<u8> = removed.iter().map(|(lod, _, _)| *lod).collect();
+        for lod in removed_lods {
+            done!("Removed LOD {}", lod);
+        }
+
+        if let (Some(bbox), Some(crs)) = (&bbox, &crs) {
+            let removed = tile_path.remove_tiles(output_path, |lod, col, row| {
+                within_bbox(crs, bbox, lod, col, row)
+            })?;
+            let mut removed_per_lod = BTreeMap::new();
+            for (lod, _, _) in removed {
+                *removed_per_lod.entry(lod).or_insert(0) += 1;
+            }
+            for (lod, removed) in removed_per_lod {
+                done!("Removed {} tiles from LOD {}", removed, lod);
+            }
+        }
+
+        let remaining_lods: BTreeSet<u8> = tile_path
+            .find_tiles(output_path)?
+            .iter()
+            .map(|((lod, _, _), _)| *lod)
+            .collect();
+        if remaining_lods.is_empty() {
+            warning!(
+                "No tiles matching {} are left in {}",
+                tile_path.url_template("{ext}"),
+                output_path.display()
+            );
+        }
+
+        if let (Some(min), Some(max)) = (remaining_lods.first(), remaining_lods.last()) {
+            crate::tilejson::update_zoom_range(output_path, *min, *max)?;
+        }
+
+        crate::output::finished(start);
+
+        Ok(())
+    }
+}
+
+fn within_bbox(crs: &CRS, bbox: &[f64; 4], lod: u8, col: u32, row: u32) -> bool {
+    let [west, south, east, north] = crs.tile_bounds(col, row, lod);
+
+    west >= bbox[0] && south >= bbox[1] && east <= bbox[2] && north <= bbox[3]
+}
+
+fn parse_bbox(value: &str) -> anyhow::Result<[f64; 4]> {
+    let values = value
+        .split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .context("Invalid bounding box")?;
+
+    match values[..] {
+        [min_x, min_y, max_x, max_y] if min_x <= max_x && min_y <= max_y => {
+            Ok([min_x, min_y, max_x, max_y])
+        }
+        _ => bail!("Bounding box has to be given as MIN_X,MIN_Y,MAX_X,MAX_Y"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::commands::Prune;
+    use crate::crs::CRS;
+    use crate::utils::TilePathTemplate;
+    use tempdir::TempDir;
+
+    fn tile_set() -> TempDir {
+        let dir = TempDir::new("meh-utils-rust-prune").unwrap();
+
+        for lod in 0..3u32 {
+            for col in 0..2u32.pow(lod) {
+                let col_path = dir.path().join(lod.to_string()).join(col.to_string());
+                fs::create_dir_all(&col_path).unwrap();
+                for row in 0..2u32.pow(lod) {
+                    fs::write(col_path.join(format!("{}.png", row)), "").unwrap();
+                }
+            }
+        }
+
+        let crs = CRS::Local {
+            world_size: 1024,
+            flip_y: true,
+            tile_extent: None,
+        };
+        crate::crs::write(dir.path(), &crs).unwrap();
+
+        dir
+    }
+
+    fn exists(dir: &Path, tile: &str) -> bool {
+        dir.join(tile).exists()
+    }
+
+    #[test]
+    fn removes_lods_outside_of_range() {
+        let dir = tile_set();
+
+        assert!((Prune {})
+            .exec(
+                dir.path(),
+                &TilePathTemplate::default(),
+                Some(1),
+                Some(1),
+                None
+            )
+            .is_ok());
+
+        assert!(!exists(dir.path(), "0"));
+        assert!(exists(dir.path(), "1/1/1.png"));
+        assert!(!exists(dir.path(), "2"));
+    }
+
+    #[test]
+    fn skips_directories_which_are_no_lod() {
+        let dir = tile_set();
+        fs::create_dir(dir.path().join("300")).unwrap();
+
+        assert!((Prune {})
+            .exec(
+                dir.path(),
+                &TilePathTemplate::default(),
+                Some(0),
+                Some(1),
+                None
+            )
+            .is_ok());
+
+        assert!(exists(dir.path(), "300"));
+        assert!(!exists(dir.path(), "2"));
+    }
+
+    #[test]
+    fn removes_tiles_within_bbox() {
+        let dir = tile_set();
+
+        // south-western quarter of the world
+        let bbox = [0.0, 0.0, 512.0, 512.0];
+        assert!((Prune {})
+            .exec(
+                dir.path(),
+                &TilePathTemplate::default(),
+                None,
+                None,
+                Some(bbox)
+            )
+            .is_ok());
+
+        assert!(exists(dir.path(), "0/0/0.png"));
+        assert!(exists(dir.path(), "1/0/0.png"));
+        assert!(!exists(dir.path(), "1/0/1.png"));
+        assert!(!exists(dir.path(), "2/1/3.png"));
+        assert!(exists(dir.path(), "2/2/3.png"));
+    }
+
+    #[test]
+    fn prunes_custom_tile_paths() {
+        let dir = TempDir::new("meh-utils-rust-prune").unwrap();
+        let tile_path = TilePathTemplate::new("tiles/{z}_{x}_{y}.png").unwrap();
+        fs::create_dir(dir.path().join("tiles")).unwrap();
+        for tile in ["0_0_0", "1_0_0", "1_1_0", "2_0_0"] {
+            fs::write(dir.path().join(format!("tiles/{}.png", tile)), "").unwrap();
+        }
+
+        assert!((Prune {})
+            .exec(dir.path(), &tile_path, Some(1), Some(1), None)
+            .is_ok());
+
+        assert!(!exists(dir.path(), "tiles/0_0_0.png"));
+        assert!(exists(dir.path(), "tiles/1_1_0.png"));
+        assert!(!exists(dir.path(), "tiles/2_0_0.png"));
+    }
+}
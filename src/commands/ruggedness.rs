@@ -0,0 +1,178 @@
+use anyhow::{bail, Context};
+use clap::{arg, App};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::commands::{
+    confirm_tile_overwrite, input_path, output_dir, parse_count, tile_writer_args,
+    tile_writer_from_args, Command,
+};
+use crate::crs::CRS;
+use crate::dem::{load_map_dem, reconcile_world_size, ruggedness_index, DEMRaster};
+use crate::utils::{build_lods, build_tile_set, calc_max_lod, pad_to_tile_grid, parse_formats};
+
+use std::path::Path;
+
+use std::time::Instant;
+
+const MEDIUM_COLOR: Rgba<u8> = Rgba([255, 200, 0, 128]);
+const ROUGH_COLOR: Rgba<u8> = Rgba([220, 40, 40, 160]);
+
+pub struct Ruggedness {}
+
+impl Command for Ruggedness {
+    fn register(&self) -> App<'static> {
+        tile_writer_args(
+            App::new("ruggedness")
+                .about("Build terrain ruggedness tiles (flat: transparent, medium: yellow, rough: red) from grad_meh data.")
+                .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory (or MEH_INPUT)").required(false))
+                .arg(arg!(-o --output <OUTPUT_DIR> "Path to output directory (or MEH_OUTPUT, default: out/<worldName>/ruggedness)").required(false))
+                .arg(arg!([INPUT] "Same as --input"))
+                .arg(arg!([OUTPUT] "Same as --output")),
+        )
+        .arg(
+            arg!(--thresholds <MEDIUM_ROUGH> "Ruggedness index in meters at which terrain counts as medium and rough")
+                .required(false)
+                .default_value("2,6"),
+        )
+        .arg(
+            arg!(--tilejson <VERSION> "TileJSON version of tile.json")
+                .required(false)
+                .possible_values(["2.2.0", "3.0.0"])
+                .default_value("2.2.0"),
+        )
+        .arg(arg!(--manifest <MAP_JSON> "Add the output to this map.json, which is created if missing").required(false))
+        .arg(arg!(--"no-dem-cache" "Always parse the DEM instead of using/writing dem.cache"))
+        .arg(arg!(--"rescale-dem" "Scale the DEM to span the worldSize of meta.json"))
+    }
+    fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
+        let start = Instant::now();
+
+        let input_path = &input_path(args)?;
+        let output_path = &output_dir(args, input_path, "ruggedness")?;
+
+        if !output_path.is_dir() {
+            bail!("Output path is not a directory");
+        }
+
+        let formats = parse_formats(args.value_of("format").unwrap())?;
+        if formats.all().iter().any(|format| !format.is_lossless()) {
+            bail!("Ruggedness tiles need a lossless format");
+        }
+        let parallel_lods = parse_count(args, "parallel-lods")?.unwrap();
+        let writer = tile_writer_from_args(args, output_path, formats)?;
+
+        let (medium, rough) = parse_thresholds(args.value_of("thresholds").unwrap())?;
+
+        step!("Loading meta.json");
+        let meta_path = input_path.join("meta.json");
+        let meta = crate::metajson::from_file(&meta_path)?;
+        done!("Loaded meta.json");
+
+        let now = Instant::now();
+        step!("Loading DEM");
+        let mut dem = load_map_dem(input_path, !args.is_present("no-dem-cache"))?;
+        done!("Loaded DEM in {}ms", now.elapsed().as_millis());
+        reconcile_world_size(&mut dem, meta.world_size, args.is_present("rescale-dem"));
+
+        let now = Instant::now();
+        step!("Calculating ruggedness index");
+        let img = calculate_image(&dem, medium, rough);
+        done!(
+            "Calculated ruggedness index in {}ms",
+            now.elapsed().as_millis()
+        );
+
+        // tiles in in-game meters need a square source with a power of two size
+        let img = pad_to_tile_grid(img, Rgba([0, 0, 0, 0]));
+        let tile_extent = dem.cell_size() as f64 * img.width() as f64;
+
+        let max_lod = calc_max_lod(&img);
+        info!("Calculated max lod: {}", max_lod);
+        writer.check_lod_range(0, max_lod)?;
+        let tile_urls = match args.value_of("tilejson") {
+            Some("3.0.0") => Some(writer.tile_urls(0, max_lod)?),
+            _ => None,
+        };
+        confirm_tile_overwrite(args, output_path, &writer, 0..=max_lod)?;
+
+        let now = Instant::now();
+        step!("Building tiles");
+        build_lods(0..=max_lod, parallel_lods, |lod| {
+            let now = Instant::now();
+            build_tile_set(&writer, &img, lod)?;
+            sub_done!(
+                "Finished tiles for LOD {} in {}ms",
+                lod,
+                now.elapsed().as_millis()
+            );
+            Ok(())
+        })?;
+        writer.finish()?;
+        done!(
+            "Built ruggedness tiles in {}ms",
+            now.elapsed().as_millis()
+        );
+        info!("{}", writer.stats());
+
+        let now = Instant::now();
+        step!("Creating tile.json");
+        let crs = CRS::new(&meta, None).with_tile_extent(tile_extent);
+        crate::crs::write(output_path, &crs)?;
+        crate::tilejson::write(output_path, 0, max_lod, &meta, crs, "Ruggedness", Vec::new())?;
+        if let Some(tile_urls) = tile_urls {
+            crate::tilejson::upgrade_to_v3(output_path, tile_urls)?;
+        }
+        if let Some(manifest_path) = args.value_of("manifest") {
+            crate::manifest::add_tile_set(
+                Path::new(manifest_path),
+                &meta,
+                "ruggedness",
+                output_path,
+            )?;
+        }
+        done!("Created tile.json in {}ms", now.elapsed().as_millis());
+
+        if let Some(failed_tiles_path) = args.value_of("failed-tiles") {
+            writer.stats().write_failures(Path::new(failed_tiles_path))?;
+        }
+        writer.stats().check_failures()?;
+        crate::output::finished(start);
+
+        Ok(())
+    }
+}
+
+fn parse_thresholds(value: &str) -> anyhow::Result<(f32, f32)> {
+    let (medium, rough) = value
+        .split_once(',')
+        .context("Thresholds have to be given as MEDIUM,ROUGH")?;
+
+    let medium: f32 = medium.trim().parse().context("Invalid medium threshold")?;
+    let rough: f32 = rough.trim().parse().context("Invalid rough threshold")?;
+
+    if medium > rough {
+        bail!("Medium threshold has to be smaller than the rough threshold");
+    }
+
+    Ok((medium, rough))
+}
+
+fn calculate_image(dem: &DEMRaster, medium: f32, rough: f32) -> DynamicImage {
+    let (w, h) = dem.dimensions();
+    let tri = ruggedness_index(dem);
+
+    let buffer = RgbaImage::from_fn(w as u32, h as u32, |x, y| {
+        let value = tri[x as usize + y as usize * w];
+
+        if value >= rough {
+            ROUGH_COLOR
+        } else if value >= medium {
+            MEDIUM_COLOR
+        } else {
+            // flat and nodata
+            Rgba([0, 0, 0, 0])
+        }
+    });
+
+    DynamicImage::ImageRgba8(buffer)
+}
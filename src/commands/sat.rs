@@ -1,81 +1,279 @@
-use anyhow::bail;
+use anyhow::{bail, Context};
 use clap::{arg, App};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 use std::path::Path;
 use std::time::Instant;
 
-use image::{imageops::replace, io::Reader as ImageReader, DynamicImage, GenericImageView};
+use image::{imageops::replace, io::Reader as ImageReader, DynamicImage, GenericImageView, Rgba};
 
-use crate::commands::Command;
-use crate::utils::{build_tile_set, calc_max_lod, TileError};
+use crate::commands::{
+    confirm_tile_overwrite, input_path, output_dir, parse_count, parse_watermark, tile_writer_args,
+    tile_writer_from_args, Command,
+};
+use crate::crs::CRS;
+use crate::utils::{
+    build_lods, build_mercator_tile_set, build_tile_set, calc_max_lod, encode_png, feather_seams,
+    limit_lod_range, match_histograms, pad_to_tile_grid, parse_formats, ColorCorrection,
+    Georeference, TileError,
+};
 
 pub struct Sat {}
 
 impl Command for Sat {
     fn register(&self) -> App<'static> {
-        App::new("sat")
-            .about("Build satellite tiles from grad_meh data.")
-            .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory"))
-            .arg(arg!(-o --output <OUTPUT_DIR> "Path to output directory"))
+        tile_writer_args(
+            App::new("sat")
+                .about("Build satellite tiles from grad_meh data.")
+                .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory (or MEH_INPUT)").required(false))
+                .arg(arg!(-o --output <OUTPUT_DIR> "Path to output directory (or MEH_OUTPUT, default: out/<worldName>/sat)").required(false))
+                .arg(arg!([INPUT] "Same as --input"))
+                .arg(arg!([OUTPUT] "Same as --output")),
+        )
+        .arg(arg!(--"min-lod" <LOD> "Don't build LODs below this one").required(false))
+        .arg(arg!(--"max-lod" <LOD> "Don't build LODs above this one").required(false))
+        .arg(
+            arg!(--tilejson <VERSION> "TileJSON version of tile.json")
+                .required(false)
+                .possible_values(["2.2.0", "3.0.0"])
+                .default_value("2.2.0"),
+        )
+        .arg(arg!(--manifest <MAP_JSON> "Add the output to this map.json, which is created if missing").required(false))
+        .arg(arg!(--"web-mercator" "Reproject tiles to EPSG:3857 using the map's latitude/longitude"))
+        .arg(arg!(--"world-file" "Also write the combined image as sat.png with world files"))
+        .arg(arg!(--"aux-xml" "Also write an .aux.xml with the coordinate system next to the world files"))
+        .arg(
+            arg!(--feather <PIXELS> "Width of the band around the seams between source images, which is blended")
+                .required(false)
+                .default_value("0"),
+        )
+        .arg(arg!(--"watermark-text" <TEXT> "Attribution stamped on low zoom tiles").required(false))
+        .arg(arg!(--"watermark-logo" <PNG> "Logo stamped on low zoom tiles").required(false))
+        .arg(
+            arg!(--"watermark-max-lod" <LOD> "Highest LOD the watermark is stamped on")
+                .required(false)
+                .default_value("2"),
+        )
+        .arg(arg!(--"equalize-tiles" "Match the color histograms of the source images before combining them"))
+        .arg(
+            arg!(--brightness <VALUE> "Added to every color channel of the satellite image (-1 to 1)")
+                .required(false)
+                .default_value("0"),
+        )
+        .arg(
+            arg!(--contrast <FACTOR> "Contrast of the satellite image (1 keeps it as it is)")
+                .required(false)
+                .default_value("1"),
+        )
+        .arg(
+            arg!(--saturation <FACTOR> "Saturation of the satellite image (0 is greyscale)")
+                .required(false)
+                .default_value("1"),
+        )
+        .arg(
+            arg!(--gamma <VALUE> "Gamma correction of the satellite image (> 1 brightens dark areas)")
+                .required(false)
+                .default_value("1"),
+        )
     }
     fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
         let start = Instant::now();
 
-        let input_path_str = args.value_of("input").unwrap();
-        let output_path_str = args.value_of("output").unwrap();
-
-        let input_path = Path::new(input_path_str);
-        let output_path = Path::new(output_path_str);
+        let input_path = &input_path(args)?;
+        let output_path = &output_dir(args, input_path, "sat")?;
 
         if !output_path.is_dir() {
             bail!("Output path is not a directory");
         }
 
-        println!("▶️  Loading meta.json");
+        let requested_min_lod = args
+            .value_of("min-lod")
+            .map(|v| v.parse::<u8>().context("Invalid min LOD"))
+            .transpose()?;
+        let requested_max_lod = args
+            .value_of("max-lod")
+            .map(|v| v.parse::<u8>().context("Invalid max LOD"))
+            .transpose()?;
+
+        let formats = parse_formats(args.value_of("format").unwrap())?;
+        let parallel_lods = parse_count(args, "parallel-lods")?.unwrap();
+        let mut writer = tile_writer_from_args(args, output_path, formats)?;
+        if let Some(watermark) = parse_watermark(args)? {
+            let max_lod = args
+                .value_of("watermark-max-lod")
+                .unwrap()
+                .parse::<u8>()
+                .context("Invalid watermark max LOD")?;
+            writer = writer.watermark(watermark, max_lod);
+        }
+        let color_correction = parse_color_correction(args)?;
+        let feather = args
+            .value_of("feather")
+            .unwrap()
+            .parse::<u32>()
+            .context("Invalid feather width")?;
+
+        step!("Loading meta.json");
         let meta_path = input_path.join("meta.json");
         let meta = crate::metajson::from_file(&meta_path)?;
-        println!("✔️  Loaded meta.json");
+        done!("Loaded meta.json");
 
         let now = Instant::now();
-        println!("▶️  Combining satellite image");
-        let combined_sat_image = load_combined_sat_image(input_path)?;
-        println!(
-            "✔️  Combined satellite image in {}ms",
+        step!("Combining satellite image");
+        let combined_sat_image =
+            load_combined_sat_image(input_path, args.is_present("equalize-tiles"), feather)?;
+        done!(
+            "Combined satellite image in {}ms",
             now.elapsed().as_millis()
         );
 
-        let max_lod = calc_max_lod(&combined_sat_image);
-        println!("ℹ️  Calculated max lod: {}", max_lod);
+        let combined_sat_image = if color_correction.is_identity() {
+            combined_sat_image
+        } else {
+            let now = Instant::now();
+            step!("Correcting colors of satellite image");
+            let corrected = color_correction.apply(combined_sat_image);
+            done!(
+                "Corrected colors of satellite image in {}ms",
+                now.elapsed().as_millis()
+            );
+            corrected
+        };
+
+        if args.is_present("world-file") {
+            let now = Instant::now();
+            step!("Writing combined satellite image");
+            let sat_path = output_path.join("sat.png");
+            encode_png(&sat_path, &combined_sat_image).map_err(|e| anyhow::anyhow!(e))?;
+            let (width, height) = combined_sat_image.dimensions();
+            crate::worldfile::write(
+                &sat_path,
+                width,
+                height,
+                meta.world_size,
+                args.is_present("aux-xml"),
+            )?;
+            done!(
+                "Wrote combined satellite image in {}ms",
+                now.elapsed().as_millis()
+            );
+        }
+
+        let georef = args
+            .is_present("web-mercator")
+            .then(|| Georeference::from_meta(&meta));
+
+        // tiles in in-game meters need a square source with a power of two size
+        let width = combined_sat_image.width();
+        let combined_sat_image = match &georef {
+            Some(_) => combined_sat_image,
+            None => pad_to_tile_grid(
+                combined_sat_image,
+                meta.color_outside_rgba().unwrap_or(Rgba([0, 0, 0, 0])),
+            ),
+        };
+        let tile_extent = meta.world_size as f64 * combined_sat_image.width() as f64 / width as f64;
+
+        let (min_lod, max_lod) = match &georef {
+            Some(georef) => georef.zoom_range(&combined_sat_image),
+            None => (0, calc_max_lod(&combined_sat_image)),
+        };
+        info!("Calculated lod range: {}-{}", min_lod, max_lod);
+        let (min_lod, max_lod) =
+            limit_lod_range((min_lod, max_lod), requested_min_lod, requested_max_lod)?;
+        if requested_min_lod.is_some() || requested_max_lod.is_some() {
+            info!("Building lod range: {}-{}", min_lod, max_lod);
+        }
+        writer.check_lod_range(min_lod, max_lod)?;
+        let tile_urls = match args.value_of("tilejson") {
+            Some("3.0.0") => Some(writer.tile_urls(min_lod, max_lod)?),
+            _ => None,
+        };
+        confirm_tile_overwrite(args, output_path, &writer, min_lod..=max_lod)?;
 
         let now = Instant::now();
-        println!("▶️  Building tiles");
-        for lod in 0..max_lod + 1 {
+        step!("Building tiles");
+        build_lods(min_lod..=max_lod, parallel_lods, |lod| {
             let now = Instant::now();
-            build_tile_set(&output_path, &combined_sat_image, lod)?;
-            println!(
-                "    ✔️  Finished tiles for LOD {} in {}ms",
+            match &georef {
+                Some(georef) => build_mercator_tile_set(&writer, &combined_sat_image, georef, lod)?,
+                None => build_tile_set(&writer, &combined_sat_image, lod)?,
+            }
+            sub_done!(
+                "Finished tiles for LOD {} in {}ms",
                 lod,
                 now.elapsed().as_millis()
             );
-        }
-        println!(
-            "✔️  Built satellite tiles in {}ms",
-            now.elapsed().as_millis()
-        );
+            Ok(())
+        })?;
+        writer.finish()?;
+        done!("Built satellite tiles in {}ms", now.elapsed().as_millis());
+        info!("{}", writer.stats());
 
         let now = Instant::now();
-        println!("▶️  Creating tile.json");
-        crate::tilejson::write(output_path, max_lod, meta, "Satellite", Vec::new())?;
-        println!("✔️  Created tile.json in {}ms", now.elapsed().as_millis());
+        step!("Creating tile.json");
+        let crs = CRS::new(&meta, georef.as_ref()).with_tile_extent(tile_extent);
+        crate::crs::write(output_path, &crs)?;
+        // LODs outside of a partial rebuild are still in the output directory
+        let (min_zoom, max_zoom) = if args.is_present("archive") {
+            (min_lod, max_lod)
+        } else {
+            crate::tilejson::merge_zoom_range(output_path, min_lod, max_lod)
+        };
+        crate::tilejson::write(
+            output_path,
+            min_zoom,
+            max_zoom,
+            &meta,
+            crs,
+            "Satellite",
+            Vec::new(),
+        )?;
+        if let Some(tile_urls) = tile_urls {
+            crate::tilejson::upgrade_to_v3(output_path, tile_urls)?;
+        }
+        if let Some(manifest_path) = args.value_of("manifest") {
+            crate::manifest::add_tile_set(Path::new(manifest_path), &meta, "sat", output_path)?;
+        }
+        done!("Created tile.json in {}ms", now.elapsed().as_millis());
 
-        println!("\n    🎉  Finished in {}ms", start.elapsed().as_millis());
+        if let Some(failed_tiles_path) = args.value_of("failed-tiles") {
+            writer.stats().write_failures(Path::new(failed_tiles_path))?;
+        }
+        writer.stats().check_failures()?;
+        crate::output::finished(start);
 
         Ok(())
     }
 }
 
-fn load_combined_sat_image(input_path: &Path) -> anyhow::Result<DynamicImage> {
+fn parse_color_correction(args: &clap::ArgMatches) -> anyhow::Result<ColorCorrection> {
+    let value = |name: &str| {
+        args.value_of(name)
+            .unwrap()
+            .parse::<f32>()
+            .with_context(|| format!("Invalid {}", name))
+    };
+
+    let correction = ColorCorrection {
+        brightness: value("brightness")?,
+        contrast: value("contrast")?,
+        saturation: value("saturation")?,
+        gamma: value("gamma")?,
+    };
+
+    if correction.gamma <= 0.0 {
+        bail!("Gamma has to be greater than zero");
+    }
+
+    Ok(correction)
+}
+
+fn load_combined_sat_image(
+    input_path: &Path,
+    equalize: bool,
+    feather: u32,
+) -> anyhow::Result<DynamicImage> {
     let sat_path = input_path.join("sat");
 
     let now = Instant::now();
@@ -97,7 +295,7 @@ fn load_combined_sat_image(input_path: &Path) -> anyhow::Result<DynamicImage> {
 
     let (ok_results, err_results): (Vec<_>, Vec<_>) = results.into_iter().partition(Result::is_ok);
 
-    if err_results.len() > 0 {
+    if !err_results.is_empty() {
         let error_string: Vec<_> = err_results
             .into_iter()
             .map(|r| format!("\t{}", r.err().unwrap()))
@@ -109,8 +307,14 @@ fn load_combined_sat_image(input_path: &Path) -> anyhow::Result<DynamicImage> {
         );
     }
 
-    let images: Vec<DynamicImage> = ok_results.into_iter().map(|r| r.unwrap()).collect();
-    println!("    ✔️  Loaded tiles in {}ms", now.elapsed().as_millis());
+    let mut images: Vec<DynamicImage> = ok_results.into_iter().map(|r| r.unwrap()).collect();
+    sub_done!("Loaded tiles in {}ms", now.elapsed().as_millis());
+
+    if equalize {
+        let now = Instant::now();
+        match_histograms(&mut images);
+        sub_done!("Equalized tiles in {}ms", now.elapsed().as_millis());
+    }
 
     let mut widths = [0u32; 4];
     let mut heights = [0u32; 4];
@@ -136,13 +340,23 @@ fn load_combined_sat_image(input_path: &Path) -> anyhow::Result<DynamicImage> {
     for col in 0..4 {
         for row in 0..4 {
             let img = &images[col * 4 + row];
-            let x = widths.iter().take(col).sum();
-            let y = heights.iter().take(row).sum();
+            let x: u32 = widths.iter().take(col).sum();
+            let y: u32 = heights.iter().take(row).sum();
 
-            replace(&mut combined_image, img, x, y);
+            replace(&mut combined_image, img, x as i64, y as i64);
+        }
+    }
+    sub_done!("Combined tiles in {}ms", now.elapsed().as_millis());
+
+    if feather > 0 {
+        let now = Instant::now();
+        let seams_x: Vec<u32> = (1..4).map(|col| widths.iter().take(col).sum()).collect();
+        let seams_y: Vec<u32> = (1..4).map(|row| heights.iter().take(row).sum()).collect();
+        if let Some(buffer) = combined_image.as_mut_rgba8() {
+            feather_seams(buffer, &seams_x, &seams_y, feather);
         }
+        sub_done!("Feathered seams in {}ms", now.elapsed().as_millis());
     }
-    println!("    ✔️  Combined tiles in {}ms", now.elapsed().as_millis());
 
     Ok(combined_image)
 }
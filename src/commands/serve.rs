@@ -0,0 +1,320 @@
+use anyhow::{bail, Context};
+use clap::{arg, App};
+use serde_json::Value;
+
+use crate::commands::{output_path, Command};
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+
+pub struct Serve {}
+
+impl Command for Serve {
+    fn register(&self) -> App<'static> {
+        App::new("serve")
+            .about("Serve an output directory over HTTP to check the tiles in a map viewer.")
+            .arg(
+                arg!(-o --output <OUTPUT_DIR> "Path to the directory to serve (or MEH_OUTPUT)")
+                    .required(false),
+            )
+            .arg(arg!([OUTPUT] "Same as --output"))
+            .arg(
+                arg!(--host <HOST> "Address to listen on")
+                    .required(false)
+                    .default_value("127.0.0.1"),
+            )
+            .arg(
+                arg!(--port <PORT> "Port to listen on")
+                    .required(false)
+                    .default_value("8080"),
+            )
+    }
+    fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
+        let root = output_path(args)?;
+        if !root.is_dir() {
+            bail!("Output path is not a directory");
+        }
+
+        let port = args
+            .value_of("port")
+            .unwrap()
+            .parse::<u16>()
+            .context("Invalid port")?;
+        let address = format!("{}:{}", args.value_of("host").unwrap(), port);
+
+        let listener = TcpListener::bind(&address)
+            .with_context(|| format!("Couldn't listen on {}", address))?;
+        info!("Serving {} on http://{}/", root.display(), address);
+
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warning!("Failed to accept connection: {}", err);
+                    continue;
+                }
+            };
+            let root = root.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = handle(stream, &root) {
+                    warning!("Failed to answer request: {}", err);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Answers a single request and closes the connection.
+fn handle(mut stream: TcpStream, root: &Path) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut host = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_owned());
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let url_path = target.split(['?', '#']).next().unwrap_or_default();
+
+    if method != "GET" && method != "HEAD" {
+        return respond(&mut stream, "405 Method Not Allowed", &[], b"", false);
+    }
+
+    let file_path = match resolve(root, url_path) {
+        // relative links of the index only work below the directory
+        Some(path) if path.is_dir() && !url_path.ends_with('/') => {
+            let location = format!("{}/", url_path);
+            info!("{} {} 301", method, url_path);
+            return respond(
+                &mut stream,
+                "301 Moved Permanently",
+                &[("Location", &location)],
+                b"",
+                method == "HEAD",
+            );
+        }
+        Some(path) if path.is_dir() && path.join("index.html").is_file() => path.join("index.html"),
+        Some(path) if path.is_file() => path,
+        _ => {
+            info!("{} {} 404", method, url_path);
+            return respond(&mut stream, "404 Not Found", &[], b"Not found", false);
+        }
+    };
+
+    let mut body = fs::read(&file_path)?;
+    let (content_type, gzipped) = content_type(&file_path, &body);
+
+    if file_path.file_name().and_then(|name| name.to_str()) == Some("tile.json") {
+        if let (Some(host), Ok(mut tile_json)) = (&host, serde_json::from_slice(&body)) {
+            let base = url_path.trim_end_matches("tile.json");
+            absolute_tile_urls(&mut tile_json, &format!("http://{}{}", host, base));
+            body = serde_json::to_vec_pretty(&tile_json)?;
+        }
+    }
+
+    let mut headers = vec![("Content-Type", content_type)];
+    if gzipped {
+        headers.push(("Content-Encoding", "gzip"));
+    }
+
+    info!("{} {} 200", method, url_path);
+    respond(&mut stream, "200 OK", &headers, &body, method == "HEAD")
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    head_only: bool,
+) -> anyhow::Result<()> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n",
+        status,
+        body.len()
+    );
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+
+    stream.write_all(response.as_bytes())?;
+    if !head_only {
+        stream.write_all(body)?;
+    }
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// File or directory below `root` the percent-encoded URL path points to.
+/// Paths leaving `root` are rejected.
+fn resolve(root: &Path, url_path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(url_path)?;
+    let relative = Path::new(decoded.trim_start_matches('/'));
+
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    Some(root.join(relative))
+}
+
+/// Decodes `%XX` escapes. Returns `None` for invalid escapes and paths,
+/// which aren't UTF-8 once decoded.
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(index + 1..index + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// Content type of a file and whether it is gzipped. Vector tiles are
+/// usually written gzipped, so .pbf files are checked for the gzip magic.
+fn content_type(path: &Path, body: &[u8]) -> (&'static str, bool) {
+    let is_gzip = body.starts_with(&[0x1f, 0x8b]);
+    let extension = |path: &Path| {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+    };
+
+    // .geojson.gz and the like are served as their inner type
+    let (extension, gzipped) = match extension(path).as_deref() {
+        Some("gz") => (extension(&path.with_extension("")), true),
+        other => (other.map(str::to_owned), is_gzip),
+    };
+
+    let content_type = match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        Some("pbf") | Some("mvt") => "application/x-protobuf",
+        Some("json") => "application/json",
+        Some("geojson") => "application/geo+json",
+        Some("html") => "text/html; charset=utf-8",
+        _ => "application/octet-stream",
+    };
+
+    (content_type, gzipped)
+}
+
+/// Makes the relative URL templates in `tiles` absolute, as map viewers
+/// resolve them against the page instead of the tile.json.
+fn absolute_tile_urls(tile_json: &mut Value, base: &str) {
+    let tiles = match tile_json.get_mut("tiles").and_then(Value::as_array_mut) {
+        Some(tiles) => tiles,
+        None => return,
+    };
+
+    for tile in tiles.iter_mut() {
+        if let Some(url) = tile.as_str().filter(|url| !url.contains("://")) {
+            *tile = Value::from(format!("{}{}", base, url.trim_start_matches("./")));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{absolute_tile_urls, content_type, percent_decode, resolve};
+    use serde_json::json;
+    use std::path::Path;
+
+    #[test]
+    fn resolves_paths_within_root() {
+        let root = Path::new("/srv/tiles");
+
+        assert_eq!(Some(root.join("3/1/2.png")), resolve(root, "/3/1/2.png"));
+        assert_eq!(None, resolve(root, "/../secret"));
+        assert_eq!(None, resolve(root, "/3/../../secret"));
+        assert_eq!(Some(root.to_path_buf()), resolve(root, "/"));
+        assert_eq!(
+            Some(root.join("Altis sat/0/0/0.png")),
+            resolve(root, "/Altis%20sat/0/0/0.png")
+        );
+        assert_eq!(None, resolve(root, "/%2e%2e/secret"));
+        assert_eq!(None, resolve(root, "/3/%2E%2E/%2E%2E/secret"));
+        assert_eq!(
+            Some(root.join("etc/passwd")),
+            resolve(root, "/%2Fetc/passwd")
+        );
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(Some(String::from("a b/ü")), percent_decode("a%20b/%C3%BC"));
+        assert_eq!(None, percent_decode("100%"));
+        assert_eq!(None, percent_decode("%zz"));
+        assert_eq!(None, percent_decode("%FF"));
+    }
+
+    #[test]
+    fn detects_content_types() {
+        assert_eq!(
+            ("image/png", false),
+            content_type(Path::new("0/0/0.png"), b"\x89PNG")
+        );
+        assert_eq!(
+            ("application/x-protobuf", true),
+            content_type(Path::new("0/0/0.pbf"), &[0x1f, 0x8b, 8])
+        );
+        assert_eq!(
+            ("application/x-protobuf", false),
+            content_type(Path::new("0/0/0.pbf"), &[0x1a, 0x02])
+        );
+        assert_eq!(
+            ("application/geo+json", true),
+            content_type(Path::new("contours_10.geojson.gz"), &[0x1f, 0x8b])
+        );
+    }
+
+    #[test]
+    fn makes_tile_urls_absolute() {
+        let mut tile_json = json!({
+            "tiles": ["{z}/{x}/{y}.png", "https://example.com/{z}/{x}/{y}.png"]
+        });
+
+        absolute_tile_urls(&mut tile_json, "http://localhost:8080/sat/");
+
+        assert_eq!(
+            json!({
+                "tiles": [
+                    "http://localhost:8080/sat/{z}/{x}/{y}.png",
+                    "https://example.com/{z}/{x}/{y}.png"
+                ]
+            }),
+            tile_json
+        );
+    }
+}
@@ -1,10 +1,17 @@
-use anyhow::bail;
+use anyhow::{bail, Context};
 use clap::{arg, App};
-use image::{DynamicImage, Rgb, RgbImage};
+use image::{DynamicImage, Rgba, RgbaImage};
 
-use crate::commands::Command;
-use crate::dem::{load_dem, DEMRaster};
-use crate::utils::{build_tile_set, calc_max_lod};
+use crate::commands::{
+    confirm_tile_overwrite, input_path, output_dir, parse_count, tile_writer_args,
+    tile_writer_from_args, Command,
+};
+use crate::crs::CRS;
+use crate::dem::{load_map_dem, reconcile_world_size, DEMRaster};
+use crate::utils::{
+    build_lods, build_mercator_tile_set, build_tile_set, calc_max_lod, limit_lod_range,
+    pad_to_tile_grid, parse_formats, Georeference,
+};
 
 use std::path::Path;
 
@@ -14,80 +21,201 @@ pub struct TerrainRGB {}
 
 impl Command for TerrainRGB {
     fn register(&self) -> App<'static> {
-        App::new("terrain_rgb")
-            .about("Build Terrain-RGB tiles from grad_meh data.")
-            .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory"))
-            .arg(arg!(-o --output <OUTPUT_DIR> "Path to output directory"))
+        tile_writer_args(
+            App::new("terrain_rgb")
+                .about("Build Terrain-RGB tiles from grad_meh data.")
+                .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory (or MEH_INPUT)").required(false))
+                .arg(arg!(-o --output <OUTPUT_DIR> "Path to output directory (or MEH_OUTPUT, default: out/<worldName>/terrain_rgb)").required(false))
+                .arg(arg!([INPUT] "Same as --input"))
+                .arg(arg!([OUTPUT] "Same as --output")),
+        )
+        .arg(arg!(--"min-lod" <LOD> "Don't build LODs below this one").required(false))
+        .arg(arg!(--"max-lod" <LOD> "Don't build LODs above this one").required(false))
+        .arg(
+            arg!(--tilejson <VERSION> "TileJSON version of tile.json")
+                .required(false)
+                .possible_values(["2.2.0", "3.0.0"])
+                .default_value("2.2.0"),
+        )
+        .arg(arg!(--manifest <MAP_JSON> "Add the output to this map.json, which is created if missing").required(false))
+        .arg(arg!(--"web-mercator" "Reproject tiles to EPSG:3857 using the map's latitude/longitude"))
+        .arg(arg!(--"no-dem-cache" "Always parse the DEM instead of using/writing dem.cache"))
+        .arg(arg!(--"rescale-dem" "Scale the DEM to span the worldSize of meta.json"))
+        .arg(
+            arg!(--"vertical-offset" <METERS> "Added to every elevation before encoding")
+                .required(false)
+                .allow_hyphen_values(true),
+        )
+        .arg(arg!(--"clamp-negative" "Encode elevations below sea level as 0"))
     }
     fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
         let start = Instant::now();
 
-        let input_path_str = args.value_of("input").unwrap();
-        let output_path_str = args.value_of("output").unwrap();
-
-        let input_path = Path::new(input_path_str);
-        let output_path = Path::new(output_path_str);
+        let input_path = &input_path(args)?;
+        let output_path = &output_dir(args, input_path, "terrain_rgb")?;
 
         if !output_path.is_dir() {
             bail!("Output path is not a directory");
         }
 
-        println!("▶️  Loading meta.json");
+        let requested_min_lod = args
+            .value_of("min-lod")
+            .map(|v| v.parse::<u8>().context("Invalid min LOD"))
+            .transpose()?;
+        let requested_max_lod = args
+            .value_of("max-lod")
+            .map(|v| v.parse::<u8>().context("Invalid max LOD"))
+            .transpose()?;
+
+        let formats = parse_formats(args.value_of("format").unwrap())?;
+        if formats.all().iter().any(|format| !format.is_lossless()) {
+            bail!("Terrain-RGB tiles need a lossless format");
+        }
+        let parallel_lods = parse_count(args, "parallel-lods")?.unwrap();
+        let writer = tile_writer_from_args(args, output_path, formats)?;
+
+        step!("Loading meta.json");
         let meta_path = input_path.join("meta.json");
         let meta = crate::metajson::from_file(&meta_path)?;
-        println!("✔️  Loaded meta.json");
+        done!("Loaded meta.json");
 
         let now = Instant::now();
-        println!("▶️  Loading DEM");
-        let dem_path = input_path.join("dem.asc.gz");
-        if !dem_path.is_file() {
-            bail!("Couldn't find dem.asc.gz");
-        }
-        let dem = load_dem(&dem_path)?;
-        println!("✔️  Loaded DEM in {}ms", now.elapsed().as_millis());
+        step!("Loading DEM");
+        let mut dem = load_map_dem(input_path, !args.is_present("no-dem-cache"))?;
+        done!("Loaded DEM in {}ms", now.elapsed().as_millis());
+        reconcile_world_size(&mut dem, meta.world_size, args.is_present("rescale-dem"));
+
+        let vertical_offset = args
+            .value_of("vertical-offset")
+            .map(|v| v.parse::<f32>().context("Invalid vertical offset"))
+            .transpose()?
+            .unwrap_or(0.0);
+        let elevation_offset = meta.elevation_offset + vertical_offset;
 
-        let elevation_offset = meta.elevation_offset;
+        let img = calculate_image(elevation_offset, &dem, args.is_present("clamp-negative"))?;
 
-        let img = calculate_image(elevation_offset, &dem)?;
+        let georef = args
+            .is_present("web-mercator")
+            .then(|| Georeference::from_meta(&meta));
 
-        let max_lod = calc_max_lod(&img);
-        println!("ℹ️  Calculated max lod: {}", max_lod);
+        // tiles in in-game meters need a square source with a power of two size
+        let img = match &georef {
+            Some(_) => img,
+            None => pad_to_tile_grid(img, no_data_pixel()),
+        };
+        let tile_extent = dem.cell_size() as f64 * img.width() as f64;
+
+        let (min_lod, max_lod) = match &georef {
+            Some(georef) => georef.zoom_range(&img),
+            None => (0, calc_max_lod(&img)),
+        };
+        info!("Calculated lod range: {}-{}", min_lod, max_lod);
+        let (min_lod, max_lod) =
+            limit_lod_range((min_lod, max_lod), requested_min_lod, requested_max_lod)?;
+        if requested_min_lod.is_some() || requested_max_lod.is_some() {
+            info!("Building lod range: {}-{}", min_lod, max_lod);
+        }
+        writer.check_lod_range(min_lod, max_lod)?;
+        let tile_urls = match args.value_of("tilejson") {
+            Some("3.0.0") => Some(writer.tile_urls(min_lod, max_lod)?),
+            _ => None,
+        };
+        confirm_tile_overwrite(args, output_path, &writer, min_lod..=max_lod)?;
 
         let now = Instant::now();
-        println!("▶️  Building tiles");
-        for lod in 0..max_lod + 1 {
+        step!("Building tiles");
+        build_lods(min_lod..=max_lod, parallel_lods, |lod| {
             let now = Instant::now();
-            build_tile_set(&output_path, &img, lod)?;
-            println!(
-                "    ✔️  Finished tiles for LOD {} in {}ms",
+            match &georef {
+                Some(georef) => build_mercator_tile_set(&writer, &img, georef, lod)?,
+                None => build_tile_set(&writer, &img, lod)?,
+            }
+            sub_done!(
+                "Finished tiles for LOD {} in {}ms",
                 lod,
                 now.elapsed().as_millis()
             );
+            Ok(())
+        })?;
+        writer.finish()?;
+        done!("Built satellite tiles in {}ms", now.elapsed().as_millis());
+        info!("{}", writer.stats());
+
+        let now = Instant::now();
+        step!("Creating tile.json");
+        let crs = CRS::new(&meta, georef.as_ref()).with_tile_extent(tile_extent);
+        crate::crs::write(output_path, &crs)?;
+        // LODs outside of a partial rebuild are still in the output directory
+        let (min_zoom, max_zoom) = if args.is_present("archive") {
+            (min_lod, max_lod)
+        } else {
+            crate::tilejson::merge_zoom_range(output_path, min_lod, max_lod)
+        };
+        crate::tilejson::write(
+            output_path,
+            min_zoom,
+            max_zoom,
+            &meta,
+            crs,
+            "Terrain-RGB",
+            Vec::new(),
+        )?;
+        crate::tilejson::append_description(output_path, NO_DATA_DESCRIPTION)?;
+        if let Some(tile_urls) = tile_urls {
+            crate::tilejson::upgrade_to_v3(output_path, tile_urls)?;
         }
-        println!(
-            "✔️  Built satellite tiles in {}ms",
-            now.elapsed().as_millis()
-        );
+        if let Some(manifest_path) = args.value_of("manifest") {
+            crate::manifest::add_tile_set(
+                Path::new(manifest_path),
+                &meta,
+                "terrain_rgb",
+                output_path,
+            )?;
+        }
+        done!("Created tile.json in {}ms", now.elapsed().as_millis());
 
-        println!("\n    🎉  Finished in {}ms", start.elapsed().as_millis());
+        if let Some(failed_tiles_path) = args.value_of("failed-tiles") {
+            writer.stats().write_failures(Path::new(failed_tiles_path))?;
+        }
+        writer.stats().check_failures()?;
+        crate::output::finished(start);
 
         Ok(())
     }
 }
 
-fn calculate_image(elevation_offset: f32, dem: &DEMRaster) -> anyhow::Result<DynamicImage> {
+const NO_DATA_DESCRIPTION: &str =
+    "Pixels without DEM data are transparent and encode an elevation of 0.";
+
+/// Cells without data become transparent. Their color still encodes sea level,
+/// so renderers which ignore alpha (and pixels blended with them while
+/// downscaling) don't end up with pits at -10000m.
+/// With `clamp_negative` elevations below sea level are encoded as 0.
+fn calculate_image(
+    elevation_offset: f32,
+    dem: &DEMRaster,
+    clamp_negative: bool,
+) -> anyhow::Result<DynamicImage> {
     let (w, h) = dem.dimensions();
-    let mut buffer = RgbImage::new(w as u32, h as u32);
+    let mut buffer = RgbaImage::new(w as u32, h as u32);
 
-    for x in 0..w {
-        for y in 0..h {
-            let elev = dem.z(x, y) + elevation_offset;
-            let pixel = elevation_to_rgb(elev);
-            buffer.put_pixel(x as u32, y as u32, pixel);
-        }
+    for (col, row, _, _, z) in dem.iter_cells() {
+        let pixel = if z == dem.no_data_value() {
+            no_data_pixel()
+        } else if clamp_negative {
+            elevation_to_rgba((z + elevation_offset).max(0.0))
+        } else {
+            elevation_to_rgba(z + elevation_offset)
+        };
+        buffer.put_pixel(col as u32, row as u32, pixel);
     }
 
-    Ok(DynamicImage::ImageRgb8(buffer))
+    Ok(DynamicImage::ImageRgba8(buffer))
+}
+
+fn no_data_pixel() -> Rgba<u8> {
+    let Rgba([r, g, b, _]) = elevation_to_rgba(0.0);
+    Rgba([r, g, b, 0])
 }
 
 /*
@@ -108,16 +236,58 @@ fn calculate_image(elevation_offset: f32, dem: &DEMRaster) -> anyhow::Result<Dyn
 */
 const MAX_X: i64 = 256_i64.pow(3) - 1;
 
-fn elevation_to_rgb(elevation: f32) -> Rgb<u8> {
+fn elevation_to_rgba(elevation: f32) -> Rgba<u8> {
     let mut x = (10.0 * elevation) as i64 + 100000 % MAX_X;
 
     let b = (x % 256) as u8;
-    x = x / 256;
+    x /= 256;
 
     let g = (x % 256) as u8;
-    x = x / 256;
+    x /= 256;
 
     let r = (x % 256) as u8;
 
-    Rgb([r, g, b])
+    Rgba([r, g, b, 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{calculate_image, elevation_to_rgba, no_data_pixel};
+    use crate::dem::{raster::Origin, DEMRaster};
+
+    #[test]
+    fn nodata_cells_are_transparent() {
+        let dem = DEMRaster::new(
+            2,
+            1,
+            Origin::Corner(0.0, 0.0),
+            1.0,
+            -9999.0,
+            vec![-9999.0, 10.0],
+        );
+
+        let img = calculate_image(5.0, &dem, false).unwrap().into_rgba8();
+
+        assert_eq!(no_data_pixel(), *img.get_pixel(0, 0));
+        assert_eq!(elevation_to_rgba(15.0), *img.get_pixel(1, 0));
+    }
+
+    #[test]
+    fn clamps_negative_elevations() {
+        let dem = DEMRaster::new(
+            2,
+            1,
+            Origin::Corner(0.0, 0.0),
+            1.0,
+            -9999.0,
+            vec![-20.0, 10.0],
+        );
+
+        let exact = calculate_image(0.0, &dem, false).unwrap().into_rgba8();
+        assert_eq!(elevation_to_rgba(-20.0), *exact.get_pixel(0, 0));
+
+        let clamped = calculate_image(0.0, &dem, true).unwrap().into_rgba8();
+        assert_eq!(elevation_to_rgba(0.0), *clamped.get_pixel(0, 0));
+        assert_eq!(elevation_to_rgba(10.0), *clamped.get_pixel(1, 0));
+    }
 }
@@ -0,0 +1,265 @@
+use anyhow::{bail, Context};
+use clap::{arg, App};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::commands::{
+    confirm_tile_overwrite, input_path, output_dir, parse_count, tile_writer_args,
+    tile_writer_from_args, Command,
+};
+use crate::crs::CRS;
+use crate::dem::{hillshade, load_map_dem, reconcile_world_size, DEMRaster};
+use crate::utils::{build_lods, build_tile_set, calc_max_lod, pad_to_tile_grid, parse_formats};
+
+use std::path::Path;
+
+use std::time::Instant;
+
+/// Water below sea level, green lowlands, brown hills and white peaks
+const DEFAULT_RAMP: &str =
+    "-100:#1f4e79,0:#9ec9e2,0.1:#5b8c3e,100:#a7b96b,300:#d8c37a,600:#a0714b,1000:#f2f2f2";
+
+pub struct Tint {}
+
+impl Command for Tint {
+    fn register(&self) -> App<'static> {
+        tile_writer_args(
+            App::new("tint")
+                .about("Build hypsometric tint tiles, coloring the DEM by elevation, from grad_meh data.")
+                .arg(arg!(-i --input <INPUT_DIR> "Path to grad_meh map directory (or MEH_INPUT)").required(false))
+                .arg(arg!(-o --output <OUTPUT_DIR> "Path to output directory (or MEH_OUTPUT, default: out/<worldName>/tint)").required(false))
+                .arg(arg!([INPUT] "Same as --input"))
+                .arg(arg!([OUTPUT] "Same as --output")),
+        )
+        .arg(
+            arg!(--ramp <STOPS> "Comma separated ELEVATION:#RRGGBB color stops (elevations include elevationOffset)")
+                .required(false)
+                .default_value(DEFAULT_RAMP),
+        )
+        .arg(arg!(--hillshade "Darken the colors by a hillshade lit from the north west"))
+        .arg(
+            arg!(--tilejson <VERSION> "TileJSON version of tile.json")
+                .required(false)
+                .possible_values(["2.2.0", "3.0.0"])
+                .default_value("2.2.0"),
+        )
+        .arg(arg!(--manifest <MAP_JSON> "Add the output to this map.json, which is created if missing").required(false))
+        .arg(arg!(--"no-dem-cache" "Always parse the DEM instead of using/writing dem.cache"))
+        .arg(arg!(--"rescale-dem" "Scale the DEM to span the worldSize of meta.json"))
+    }
+    fn run(&self, args: &clap::ArgMatches) -> anyhow::Result<()> {
+        let start = Instant::now();
+
+        let input_path = &input_path(args)?;
+        let output_path = &output_dir(args, input_path, "tint")?;
+
+        if !output_path.is_dir() {
+            bail!("Output path is not a directory");
+        }
+
+        let formats = parse_formats(args.value_of("format").unwrap())?;
+        let parallel_lods = parse_count(args, "parallel-lods")?.unwrap();
+        let writer = tile_writer_from_args(args, output_path, formats)?;
+
+        let ramp = parse_ramp(args.value_of("ramp").unwrap())?;
+
+        step!("Loading meta.json");
+        let meta_path = input_path.join("meta.json");
+        let meta = crate::metajson::from_file(&meta_path)?;
+        done!("Loaded meta.json");
+
+        let now = Instant::now();
+        step!("Loading DEM");
+        let mut dem = load_map_dem(input_path, !args.is_present("no-dem-cache"))?;
+        done!("Loaded DEM in {}ms", now.elapsed().as_millis());
+        reconcile_world_size(&mut dem, meta.world_size, args.is_present("rescale-dem"));
+
+        let now = Instant::now();
+        step!("Coloring DEM");
+        let img = calculate_image(
+            &dem,
+            &ramp,
+            meta.elevation_offset,
+            args.is_present("hillshade"),
+        );
+        done!("Colored DEM in {}ms", now.elapsed().as_millis());
+
+        // tiles in in-game meters need a square source with a power of two size
+        let img = pad_to_tile_grid(img, Rgba([0, 0, 0, 0]));
+        let tile_extent = dem.cell_size() as f64 * img.width() as f64;
+
+        let max_lod = calc_max_lod(&img);
+        info!("Calculated max lod: {}", max_lod);
+        writer.check_lod_range(0, max_lod)?;
+        let tile_urls = match args.value_of("tilejson") {
+            Some("3.0.0") => Some(writer.tile_urls(0, max_lod)?),
+            _ => None,
+        };
+        confirm_tile_overwrite(args, output_path, &writer, 0..=max_lod)?;
+
+        let now = Instant::now();
+        step!("Building tiles");
+        build_lods(0..=max_lod, parallel_lods, |lod| {
+            let now = Instant::now();
+            build_tile_set(&writer, &img, lod)?;
+            sub_done!(
+                "Finished tiles for LOD {} in {}ms",
+                lod,
+                now.elapsed().as_millis()
+            );
+            Ok(())
+        })?;
+        writer.finish()?;
+        done!("Built tint tiles in {}ms", now.elapsed().as_millis());
+        info!("{}", writer.stats());
+
+        let now = Instant::now();
+        step!("Creating tile.json");
+        let crs = CRS::new(&meta, None).with_tile_extent(tile_extent);
+        crate::crs::write(output_path, &crs)?;
+        crate::tilejson::write(
+            output_path,
+            0,
+            max_lod,
+            &meta,
+            crs,
+            "Hypsometric tint",
+            Vec::new(),
+        )?;
+        if let Some(tile_urls) = tile_urls {
+            crate::tilejson::upgrade_to_v3(output_path, tile_urls)?;
+        }
+        if let Some(manifest_path) = args.value_of("manifest") {
+            crate::manifest::add_tile_set(Path::new(manifest_path), &meta, "tint", output_path)?;
+        }
+        done!("Created tile.json in {}ms", now.elapsed().as_millis());
+
+        if let Some(failed_tiles_path) = args.value_of("failed-tiles") {
+            writer.stats().write_failures(Path::new(failed_tiles_path))?;
+        }
+        writer.stats().check_failures()?;
+        crate::output::finished(start);
+
+        Ok(())
+    }
+}
+
+/// Parses color stops like `0:#9ec9e2,100:#a7b96b`, sorted by elevation.
+fn parse_ramp(value: &str) -> anyhow::Result<Vec<(f32, [u8; 3])>> {
+    let mut stops = value
+        .split(',')
+        .map(|stop| {
+            let (elevation, color) = stop
+                .split_once(':')
+                .with_context(|| format!("Color stop {} has to be ELEVATION:#RRGGBB", stop))?;
+            let elevation: f32 = elevation
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid elevation in color stop {}", stop))?;
+            Ok((elevation, parse_color(color.trim())?))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    Ok(stops)
+}
+
+fn parse_color(value: &str) -> anyhow::Result<[u8; 3]> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if hex.len() != 6 || !hex.is_ascii() {
+        bail!("Invalid color {}, expected #RRGGBB", value);
+    }
+
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .with_context(|| format!("Invalid color {}, expected #RRGGBB", value))
+    };
+
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Color of `elevation`, linearly interpolated between the surrounding stops.
+/// Elevations outside of the ramp get the color of the closest stop.
+fn ramp_color(ramp: &[(f32, [u8; 3])], elevation: f32) -> [u8; 3] {
+    let upper = ramp.partition_point(|(stop, _)| *stop <= elevation);
+
+    if upper == 0 {
+        return ramp[0].1;
+    }
+    if upper == ramp.len() {
+        return ramp[upper - 1].1;
+    }
+
+    let (low, low_color) = ramp[upper - 1];
+    let (high, high_color) = ramp[upper];
+    let t = (elevation - low) / (high - low);
+
+    let mut color = [0; 3];
+    for i in 0..3 {
+        let (a, b) = (low_color[i] as f32, high_color[i] as f32);
+        color[i] = (a + (b - a) * t).round() as u8;
+    }
+    color
+}
+
+fn calculate_image(
+    dem: &DEMRaster,
+    ramp: &[(f32, [u8; 3])],
+    elevation_offset: f32,
+    with_hillshade: bool,
+) -> DynamicImage {
+    let (w, h) = dem.dimensions();
+    let shade = if with_hillshade {
+        Some(hillshade(dem))
+    } else {
+        None
+    };
+
+    let buffer = RgbaImage::from_fn(w as u32, h as u32, |x, y| {
+        let (col, row) = (x as usize, y as usize);
+        let z = match dem.try_z(col, row) {
+            Some(z) => z,
+            None => return Rgba([0, 0, 0, 0]),
+        };
+
+        let [r, g, b] = ramp_color(ramp, z + elevation_offset);
+        match &shade {
+            Some(shade) => {
+                let s = shade[col + row * w];
+                let multiply = |c: u8| (c as f32 * s).round() as u8;
+                Rgba([multiply(r), multiply(g), multiply(b), 255])
+            }
+            None => Rgba([r, g, b, 255]),
+        }
+    });
+
+    DynamicImage::ImageRgba8(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_color, parse_ramp, ramp_color, DEFAULT_RAMP};
+
+    #[test]
+    fn parses_ramps() {
+        assert_eq!(
+            vec![(-5.0, [0, 0, 255]), (10.0, [0x1f, 0x4e, 0x79])],
+            parse_ramp("10:#1f4e79, -5:0000ff").unwrap()
+        );
+        assert!(parse_ramp(DEFAULT_RAMP).is_ok());
+        assert!(parse_ramp("10").is_err());
+        assert!(parse_ramp("a:#000000").is_err());
+        assert!(parse_color("#12345").is_err());
+        assert!(parse_color("#gg0000").is_err());
+    }
+
+    #[test]
+    fn interpolates_between_stops() {
+        let ramp = vec![(0.0, [0, 0, 0]), (100.0, [200, 100, 50])];
+
+        assert_eq!([0, 0, 0], ramp_color(&ramp, -50.0));
+        assert_eq!([100, 50, 25], ramp_color(&ramp, 50.0));
+        assert_eq!([200, 100, 50], ramp_color(&ramp, 100.0));
+        assert_eq!([200, 100, 50], ramp_color(&ramp, 500.0));
+    }
+}
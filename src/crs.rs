@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use serde_json::to_string_pretty;
+
+use std::{
+    fs::File,
+    io::{BufReader, Error, Write},
+    path::Path,
+};
+
+use crate::metajson::MetaJSON;
+use crate::utils::Georeference;
+
+/// Coordinate reference system of a tile set. Written to `crs.json` and
+/// embedded as `crs` in tile.json.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[allow(clippy::upper_case_acronyms)]
+pub enum CRS {
+    /*
+        In-game meters. Every LOD z spans tileExtent meters (the whole world
+        unless the source was padded, see pad_to_tile_grid) with 2^z × 2^z
+        tiles and tile row 0 is at the northern edge, so the y-axis is flipped:
+
+        tile_x = x / tileExtent * 2^z
+        tile_y = (worldSize - y) / tileExtent * 2^z
+    */
+    #[serde(rename = "arma", rename_all = "camelCase")]
+    Local {
+        world_size: u32,
+        flip_y: bool,
+        /// only present if it differs from world_size
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tile_extent: Option<f64>,
+    },
+
+    /*
+        Regular EPSG:3857 slippy-map tiles. The world's center is anchored at
+        `center` (longitude, latitude) and in-game meters map linearly to
+        degrees around it (see Georeference).
+    */
+    #[serde(rename = "EPSG:3857", rename_all = "camelCase")]
+    WebMercator {
+        world_size: u32,
+        center: [f64; 2],
+        bounds: [f64; 4],
+    },
+}
+
+impl CRS {
+    pub fn new(meta: &MetaJSON, georef: Option<&Georeference>) -> Self {
+        match georef {
+            Some(georef) => {
+                let half = meta.world_size as f64 / 2.0;
+                let (lon, lat) = georef.lon_lat(half, half);
+
+                CRS::WebMercator {
+                    world_size: meta.world_size,
+                    center: [lon, lat],
+                    bounds: georef.bounds(),
+                }
+            }
+            None => CRS::Local {
+                world_size: meta.world_size,
+                flip_y: true,
+                tile_extent: None,
+            },
+        }
+    }
+
+    /// Sets the meters covered by tile 0/0/0, if the tiles don't span exactly
+    /// the world. Only applies to in-game meters.
+    pub fn with_tile_extent(mut self, extent: f64) -> Self {
+        if let CRS::Local {
+            world_size,
+            tile_extent,
+            ..
+        } = &mut self
+        {
+            *tile_extent = (extent != *world_size as f64).then_some(extent);
+        }
+        self
+    }
+
+    /// West, south, east and north edge of a tile in in-game meters.
+    pub fn tile_bounds(&self, col: u32, row: u32, lod: u8) -> [f64; 4] {
+        match self {
+            CRS::Local {
+                world_size,
+                tile_extent,
+                ..
+            } => {
+                let extent = tile_extent.unwrap_or(*world_size as f64);
+                let tile_size = extent / 2f64.powi(lod as i32);
+                let north = *world_size as f64 - row as f64 * tile_size;
+
+                [
+                    col as f64 * tile_size,
+                    north - tile_size,
+                    (col + 1) as f64 * tile_size,
+                    north,
+                ]
+            }
+            CRS::WebMercator {
+                world_size, center, ..
+            } => Georeference::new(*world_size as f64, center[0], center[1])
+                .tile_bounds(col, row, lod),
+        }
+    }
+}
+
+pub fn from_file(path: &Path) -> anyhow::Result<CRS> {
+    let reader = BufReader::new(File::open(path)?);
+
+    Ok(serde_json::from_reader(reader)?)
+}
+
+pub fn write(dir: &Path, crs: &CRS) -> Result<(), Error> {
+    let mut file = File::create(dir.join("crs.json"))?;
+    let json = to_string_pretty(crs)?;
+
+    file.write_all(json.as_bytes())
+}
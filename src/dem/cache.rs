@@ -0,0 +1,223 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use super::{raster::Origin, DEMRaster};
+
+/*
+    Layout of a DEM cache file (all numbers little endian):
+
+    magic          4 bytes   "MDEM"
+    version        u8
+    flags          u8        bit 0: grid is zstd compressed
+    source length  u64       size of the dem.asc.gz the cache was built from
+    source mtime   u64       modification time (seconds since epoch) of that file
+    columns        u32
+    rows           u32
+    left           f32
+    bottom         f32
+    cell size      f32
+    nodata value   f32
+    grid           columns * rows f32 values, row by row
+*/
+const MAGIC: &[u8; 4] = b"MDEM";
+const VERSION: u8 = 1;
+const FLAG_ZSTD: u8 = 1;
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(thiserror::Error, Debug)]
+pub enum DEMCacheError {
+    #[error("Not a DEM cache file")]
+    InvalidMagic,
+
+    #[error("Unsupported DEM cache version {}", .0)]
+    UnsupportedVersion(u8),
+
+    #[error("DEM cache grid doesn't match its header")]
+    SizeMismatch,
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Identifies the exact source file a cache was built from.
+#[derive(Debug, PartialEq)]
+pub struct SourceStamp {
+    len: u64,
+    modified: u64,
+}
+
+impl SourceStamp {
+    pub fn from_file(path: &Path) -> std::io::Result<Self> {
+        let meta = fs::metadata(path)?;
+        let modified = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        Ok(SourceStamp {
+            len: meta.len(),
+            modified,
+        })
+    }
+}
+
+pub fn write_cache(
+    path: &Path,
+    raster: &DEMRaster,
+    source: &SourceStamp,
+    compress: bool,
+) -> Result<(), DEMCacheError> {
+    let (columns, rows) = raster.dimensions();
+    let (left, bottom) = raster.lower_left();
+
+    let mut grid: Vec<u8> = Vec::with_capacity(raster.get_data().len() * 4);
+    for z in raster.get_data() {
+        grid.extend_from_slice(&z.to_le_bytes());
+    }
+    if compress {
+        grid = zstd::stream::encode_all(&grid[..], ZSTD_LEVEL)?;
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION, if compress { FLAG_ZSTD } else { 0 }])?;
+    out.write_all(&source.len.to_le_bytes())?;
+    out.write_all(&source.modified.to_le_bytes())?;
+    out.write_all(&(columns as u32).to_le_bytes())?;
+    out.write_all(&(rows as u32).to_le_bytes())?;
+    out.write_all(&left.to_le_bytes())?;
+    out.write_all(&bottom.to_le_bytes())?;
+    out.write_all(&raster.cell_size().to_le_bytes())?;
+    out.write_all(&raster.no_data_value().to_le_bytes())?;
+    out.write_all(&grid)?;
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Reads the cache at `path`. Returns `None` if there is no cache or if it
+/// was built from a different source file.
+pub fn read_cache(path: &Path, source: &SourceStamp) -> Result<Option<DEMRaster>, DEMCacheError> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let mut input = BufReader::new(File::open(path)?);
+
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(DEMCacheError::InvalidMagic);
+    }
+
+    let mut version_and_flags = [0u8; 2];
+    input.read_exact(&mut version_and_flags)?;
+    let [version, flags] = version_and_flags;
+    if version != VERSION {
+        return Err(DEMCacheError::UnsupportedVersion(version));
+    }
+
+    let stamp = SourceStamp {
+        len: u64::from_le_bytes(read_bytes(&mut input)?),
+        modified: u64::from_le_bytes(read_bytes(&mut input)?),
+    };
+    if &stamp != source {
+        return Ok(None);
+    }
+
+    let columns = u32::from_le_bytes(read_bytes(&mut input)?) as usize;
+    let rows = u32::from_le_bytes(read_bytes(&mut input)?) as usize;
+    let left = f32::from_le_bytes(read_bytes(&mut input)?);
+    let bottom = f32::from_le_bytes(read_bytes(&mut input)?);
+    let cell_size = f32::from_le_bytes(read_bytes(&mut input)?);
+    let no_data_value = f32::from_le_bytes(read_bytes(&mut input)?);
+
+    let mut grid = Vec::new();
+    input.read_to_end(&mut grid)?;
+    if flags & FLAG_ZSTD != 0 {
+        grid = zstd::stream::decode_all(&grid[..])?;
+    }
+
+    if grid.len() != columns * rows * 4 {
+        return Err(DEMCacheError::SizeMismatch);
+    }
+
+    let data: Vec<f32> = grid
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+
+    Ok(Some(DEMRaster::new(
+        columns,
+        rows,
+        Origin::Corner(left, bottom),
+        cell_size,
+        no_data_value,
+        data,
+    )))
+}
+
+fn read_bytes<const N: usize>(input: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    input.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_cache, write_cache, SourceStamp};
+    use crate::dem::{raster::Origin, DEMRaster};
+    use tempdir::TempDir;
+
+    fn raster() -> DEMRaster {
+        DEMRaster::new(
+            3,
+            2,
+            Origin::Corner(200000.0, 0.0),
+            10.0,
+            -9999.0,
+            vec![1.0, 2.5, -3.0, 4.0, -9999.0, 6.25],
+        )
+    }
+
+    fn stamp(len: u64) -> SourceStamp {
+        SourceStamp { len, modified: 42 }
+    }
+
+    #[test]
+    fn roundtrips_raw_and_compressed() {
+        let dir = TempDir::new("meh-utils-rust-dem-cache").unwrap();
+
+        for compress in [false, true] {
+            let path = dir.path().join(format!("dem_{}.cache", compress));
+            write_cache(&path, &raster(), &stamp(1), compress).unwrap();
+
+            assert_eq!(Some(raster()), read_cache(&path, &stamp(1)).unwrap());
+        }
+    }
+
+    #[test]
+    fn ignores_stale_or_missing_cache() {
+        let dir = TempDir::new("meh-utils-rust-dem-cache").unwrap();
+        let path = dir.path().join("dem.cache");
+
+        assert_eq!(None, read_cache(&path, &stamp(1)).unwrap());
+
+        write_cache(&path, &raster(), &stamp(1), true).unwrap();
+        assert_eq!(None, read_cache(&path, &stamp(2)).unwrap());
+    }
+
+    #[test]
+    fn rejects_foreign_files() {
+        let dir = TempDir::new("meh-utils-rust-dem-cache").unwrap();
+        let path = dir.path().join("dem.cache");
+        std::fs::write(&path, "ncols 3\nnrows 2\n").unwrap();
+
+        assert!(read_cache(&path, &stamp(1)).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::DEMRaster;
+
+/// Edge between the cell (`col`, `row`) and its neighbor to the right
+/// (`true`) or below (`false`).
+type Edge = (usize, usize, bool);
+
+/// Contour lines of `dem` at the elevation `level` as lists of world
+/// positions, traced with marching squares between the cell positions.
+/// Squares with nodata cells are left out, so lines end at nodata just like
+/// at the border of the raster. Closed lines end with their first position.
+///
+/// Lines run with the higher terrain on their right, so rings around peaks
+/// are clockwise and rings around depressions counterclockwise.
+pub fn contour_lines(dem: &DEMRaster, level: f32) -> Vec<Vec<(f32, f32)>> {
+    let (columns, rows) = dem.dimensions();
+    let mut crossings: HashMap<Edge, (f32, f32)> = HashMap::new();
+    let mut segments: Vec<(Edge, Edge)> = Vec::new();
+
+    for row in 0..rows.saturating_sub(1) {
+        for col in 0..columns.saturating_sub(1) {
+            let corners = [
+                (col, row),
+                (col + 1, row),
+                (col + 1, row + 1),
+                (col, row + 1),
+            ];
+            let z = match corners.map(|(c, r)| dem.try_z(c, r)) {
+                [Some(a), Some(b), Some(c), Some(d)] => [a, b, c, d],
+                _ => continue,
+            };
+            let above = z.map(|z| z >= level);
+            let position = |corner: usize| {
+                let (c, r) = corners[corner];
+                (dem.x(c), dem.y(r))
+            };
+
+            // top, right, bottom and left edge, each with its two corners
+            let edges = [
+                ((col, row, true), 0, 1),
+                ((col + 1, row, false), 1, 2),
+                ((col, row + 1, true), 3, 2),
+                ((col, row, false), 0, 3),
+            ];
+            let crossed: Vec<Edge> = edges
+                .iter()
+                .filter(|(_, from, to)| above[*from] != above[*to])
+                .map(|(edge, from, to)| {
+                    crossings.entry(*edge).or_insert_with(|| {
+                        let (from_col, from_row) = corners[*from];
+                        let (to_col, to_row) = corners[*to];
+                        let t = (level - z[*from]) / (z[*to] - z[*from]);
+                        (
+                            dem.x(from_col) + (dem.x(to_col) - dem.x(from_col)) * t,
+                            dem.y(from_row) + (dem.y(to_row) - dem.y(from_row)) * t,
+                        )
+                    });
+                    *edge
+                })
+                .collect();
+
+            // points from the lower to the higher terrain next to a segment
+            let uphill = |high: (f32, f32), low: (f32, f32)| (high.0 - low.0, high.1 - low.1);
+            let mut push = |a: Edge, b: Edge, uphill: (f32, f32)| {
+                let (from, to) = (crossings[&a], crossings[&b]);
+                let cross = (to.0 - from.0) * uphill.1 - (to.1 - from.1) * uphill.0;
+                segments.push(if cross > 0.0 { (b, a) } else { (a, b) });
+            };
+
+            match crossed[..] {
+                [a, b] => {
+                    let mean = |is_above: bool| {
+                        let corners: Vec<_> = (0..4).filter(|i| above[*i] == is_above).collect();
+                        let n = corners.len() as f32;
+                        corners
+                            .iter()
+                            .map(|i| position(*i))
+                            .fold((0.0, 0.0), |sum, p| (sum.0 + p.0 / n, sum.1 + p.1 / n))
+                    };
+                    push(a, b, uphill(mean(true), mean(false)));
+                }
+                [top, right, bottom, left] => {
+                    // saddle: the center decides which corners are connected,
+                    // each segment cuts off one corner
+                    let center = (
+                        (position(0).0 + position(2).0) / 2.0,
+                        (position(0).1 + position(2).1) / 2.0,
+                    );
+                    let mut cut_off = |a: Edge, b: Edge, corner: usize| {
+                        if above[corner] {
+                            push(a, b, uphill(position(corner), center));
+                        } else {
+                            push(a, b, uphill(center, position(corner)));
+                        }
+                    };
+                    let center_above = z.iter().sum::<f32>() / 4.0 >= level;
+                    if center_above == above[0] {
+                        cut_off(top, right, 1);
+                        cut_off(bottom, left, 3);
+                    } else {
+                        cut_off(left, top, 0);
+                        cut_off(right, bottom, 2);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    join(&segments)
+        .into_iter()
+        .map(|line| line.iter().map(|edge| crossings[edge]).collect())
+        .collect()
+}
+
+/// Chains segments sharing an edge into lines. Every edge belongs to at most
+/// two squares and is crossed at most once per square, so there is never
+/// more than one segment to continue with. As neighboring segments agree on
+/// the uphill side, the lines keep the direction of their segments.
+fn join(segments: &[(Edge, Edge)]) -> Vec<VecDeque<Edge>> {
+    let mut by_edge: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (index, (from, to)) in segments.iter().enumerate() {
+        by_edge.entry(*from).or_default().push(index);
+        by_edge.entry(*to).or_default().push(index);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let next = |edge: Edge, used: &mut Vec<bool>| -> Option<Edge> {
+        let index = *by_edge[&edge].iter().find(|index| !used[**index])?;
+        used[index] = true;
+        let (from, to) = segments[index];
+        Some(if from == edge { to } else { from })
+    };
+
+    let mut lines = Vec::new();
+    for index in 0..segments.len() {
+        if used[index] {
+            continue;
+        }
+        used[index] = true;
+
+        let (from, to) = segments[index];
+        let mut line = VecDeque::from([from, to]);
+        let mut end = to;
+        while let Some(edge) = next(end, &mut used) {
+            line.push_back(edge);
+            end = edge;
+        }
+        let mut start = from;
+        while let Some(edge) = next(start, &mut used) {
+            line.push_front(edge);
+            start = edge;
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::contour_lines;
+    use crate::dem::raster::{DEMRaster, Origin};
+
+    fn peak(no_data: f32) -> DEMRaster {
+        #[rustfmt::skip]
+        let data = vec![
+            0.0, 0.0, 0.0, 0.0,
+            0.0, 10.0, 0.0, no_data,
+            0.0, 0.0, 0.0, 0.0,
+        ];
+
+        DEMRaster::new(4, 3, Origin::Corner(0.0, 0.0), 2.0, -9999.0, data)
+    }
+
+    #[test]
+    fn traces_closed_lines_around_peaks() {
+        let lines = contour_lines(&peak(0.0), 5.0);
+
+        assert_eq!(1, lines.len());
+        let line = &lines[0];
+        assert_eq!(5, line.len());
+        assert_eq!(line.first(), line.last());
+        // half way between the peak at (2, 4) and its neighbors
+        for (x, y) in &line[..4] {
+            assert_eq!(1.0, (x - 2.0).abs() + (y - 4.0).abs());
+        }
+    }
+
+    /// Twice the area enclosed by `ring`, negative if clockwise.
+    fn signed_area(ring: &[(f32, f32)]) -> f32 {
+        ring.windows(2)
+            .map(|pair| pair[0].0 * pair[1].1 - pair[1].0 * pair[0].1)
+            .sum()
+    }
+
+    #[test]
+    fn keeps_higher_terrain_on_the_right() {
+        let rings = contour_lines(&peak(0.0), 5.0);
+        assert!(signed_area(&rings[0]) < 0.0);
+
+        let depression: Vec<f32> = peak(0.0).get_data().iter().map(|z| -z).collect();
+        let dem = DEMRaster::new(4, 3, Origin::Corner(0.0, 0.0), 2.0, -9999.0, depression);
+        let rings = contour_lines(&dem, -5.0);
+        assert!(signed_area(&rings[0]) > 0.0);
+
+        // rising to the east, so the line has to run north
+        let slope = DEMRaster::new(
+            2,
+            2,
+            Origin::Corner(0.0, 0.0),
+            1.0,
+            -9999.0,
+            vec![0.0, 10.0, 0.0, 10.0],
+        );
+        let lines = contour_lines(&slope, 5.0);
+        assert_eq!(vec![(0.5, 1.0), (0.5, 2.0)], lines[0]);
+    }
+
+    #[test]
+    fn skips_squares_with_nodata() {
+        let lines = contour_lines(&peak(-9999.0), 5.0);
+
+        assert_eq!(1, lines.len());
+        assert_eq!(5, lines[0].len());
+        // would enclose the nodata cell otherwise
+        assert!(contour_lines(&peak(-9999.0), -5000.0).is_empty());
+    }
+}
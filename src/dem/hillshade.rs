@@ -0,0 +1,95 @@
+use super::DEMRaster;
+
+/// Direction of the light, clockwise from north (in degrees)
+const AZIMUTH: f32 = 315.0;
+/// Height of the light above the horizon (in degrees)
+const ALTITUDE: f32 = 45.0;
+
+/// Illumination (0-1) of every cell lit from the north west, using Horn's
+/// slope and aspect of the 3x3 window around it. Missing neighbors (at the
+/// border or nodata) are taken as level with the cell. Nodata cells yield `NaN`.
+pub fn hillshade(dem: &DEMRaster) -> Vec<f32> {
+    let (columns, rows) = dem.dimensions();
+    let cell_size = dem.cell_size();
+
+    let zenith = (90.0 - ALTITUDE).to_radians();
+    let azimuth = (360.0 - AZIMUTH + 90.0).to_radians();
+
+    let mut shade = Vec::with_capacity(columns * rows);
+
+    for row in 0..rows {
+        for col in 0..columns {
+            let z = match dem.try_z(col, row) {
+                Some(z) => z,
+                None => {
+                    shade.push(f32::NAN);
+                    continue;
+                }
+            };
+
+            // elevation of the neighbor `dc` columns and `dr` rows away
+            let n = |dc: isize, dr: isize| {
+                let c = col.checked_add_signed(dc);
+                let r = row.checked_add_signed(dr);
+                match (c, r) {
+                    (Some(c), Some(r)) => dem.try_z(c, r).unwrap_or(z),
+                    _ => z,
+                }
+            };
+
+            let dz_dx = ((n(1, -1) + 2.0 * n(1, 0) + n(1, 1))
+                - (n(-1, -1) + 2.0 * n(-1, 0) + n(-1, 1)))
+                / (8.0 * cell_size);
+            // rows go from north to south
+            let dz_dy = ((n(-1, 1) + 2.0 * n(0, 1) + n(1, 1))
+                - (n(-1, -1) + 2.0 * n(0, -1) + n(1, -1)))
+                / (8.0 * cell_size);
+
+            let slope = dz_dx.hypot(dz_dy).atan();
+            let aspect = dz_dy.atan2(-dz_dx);
+
+            let value =
+                zenith.cos() * slope.cos() + zenith.sin() * slope.sin() * (azimuth - aspect).cos();
+            shade.push(value.clamp(0.0, 1.0));
+        }
+    }
+
+    shade
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hillshade;
+    use crate::dem::raster::{DEMRaster, Origin};
+
+    fn slope(rise_to_east: f32, rise_to_south: f32) -> DEMRaster {
+        let data = (0..9)
+            .map(|index| (index % 3) as f32 * rise_to_east + (index / 3) as f32 * rise_to_south)
+            .collect();
+
+        DEMRaster::new(3, 3, Origin::Corner(0.0, 0.0), 1.0, -9999.0, data)
+    }
+
+    #[test]
+    fn lights_slopes_facing_the_sun() {
+        let flat = hillshade(&slope(0.0, 0.0))[4];
+
+        assert!((flat - 45f32.to_radians().cos()).abs() < 1e-6);
+        assert!(hillshade(&slope(1.0, 0.0))[4] > flat, "facing west");
+        assert!(hillshade(&slope(-1.0, 0.0))[4] < flat, "facing east");
+        assert!(hillshade(&slope(0.0, 1.0))[4] > flat, "facing north");
+        assert!(hillshade(&slope(0.0, -1.0))[4] < flat, "facing south");
+    }
+
+    #[test]
+    fn skips_nodata() {
+        let mut data = vec![0.0; 9];
+        data[0] = -9999.0;
+        let dem = DEMRaster::new(3, 3, Origin::Corner(0.0, 0.0), 1.0, -9999.0, data);
+
+        let shade = hillshade(&dem);
+
+        assert!(shade[0].is_nan());
+        assert!(shade[1..].iter().all(|s| (0.0..=1.0).contains(s)));
+    }
+}
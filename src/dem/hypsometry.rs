@@ -0,0 +1,136 @@
+use serde::Serialize;
+
+use super::DEMRaster;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct HistogramBin {
+    /// lower bound of the bin in meters
+    pub elevation: f32,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct CurvePoint {
+    pub elevation: f32,
+    /// share of the area (0-1) which lies at or above `elevation`
+    pub area_above: f32,
+}
+
+/// Elevation distribution of a DEM.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ElevationDistribution {
+    pub min: f32,
+    pub max: f32,
+    pub bin_size: f32,
+    pub histogram: Vec<HistogramBin>,
+    pub hypsometric_curve: Vec<CurvePoint>,
+}
+
+/// Builds an elevation histogram with bins of `bin_size` meters and the
+/// matching hypsometric curve. Nodata cells are ignored. Returns `None` if
+/// the DEM has no data at all.
+pub fn hypsometry(
+    dem: &DEMRaster,
+    bin_size: f32,
+    elevation_offset: f32,
+) -> Option<ElevationDistribution> {
+    let no_data = dem.no_data_value();
+    let elevations: Vec<f32> = dem
+        .get_data()
+        .iter()
+        .filter(|z| **z != no_data)
+        .map(|z| z + elevation_offset)
+        .collect();
+
+    let min = elevations.iter().copied().reduce(f32::min)?;
+    let max = elevations.iter().copied().reduce(f32::max)?;
+
+    let first_bin = (min / bin_size).floor();
+    let bin_count = ((max / bin_size).floor() - first_bin) as usize + 1;
+
+    let mut counts = vec![0usize; bin_count];
+    for z in &elevations {
+        let bin = ((z / bin_size).floor() - first_bin) as usize;
+        counts[bin.min(bin_count - 1)] += 1;
+    }
+
+    let histogram: Vec<HistogramBin> = counts
+        .iter()
+        .enumerate()
+        .map(|(i, count)| HistogramBin {
+            elevation: (first_bin + i as f32) * bin_size,
+            count: *count,
+        })
+        .collect();
+
+    let total = elevations.len() as f32;
+    let mut above = elevations.len();
+    let hypsometric_curve = histogram
+        .iter()
+        .map(|bin| {
+            let point = CurvePoint {
+                elevation: bin.elevation.max(min),
+                area_above: above as f32 / total,
+            };
+            above -= bin.count;
+            point
+        })
+        .chain(std::iter::once(CurvePoint {
+            elevation: max,
+            area_above: 0.0,
+        }))
+        .collect();
+
+    Some(ElevationDistribution {
+        min,
+        max,
+        bin_size,
+        histogram,
+        hypsometric_curve,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hypsometry;
+    use crate::dem::{raster::Origin, DEMRaster};
+
+    #[test]
+    fn bins_elevations() {
+        let dem = DEMRaster::new(
+            3,
+            2,
+            Origin::Corner(0.0, 0.0),
+            1.0,
+            -9999.0,
+            vec![1.0, 4.0, 12.0, 15.0, 25.0, -9999.0],
+        );
+
+        let result = hypsometry(&dem, 10.0, 0.0).unwrap();
+
+        assert_eq!((1.0, 25.0), (result.min, result.max));
+        let counts: Vec<_> = result
+            .histogram
+            .iter()
+            .map(|b| (b.elevation, b.count))
+            .collect();
+        assert_eq!(vec![(0.0, 2), (10.0, 2), (20.0, 1)], counts);
+
+        let curve: Vec<_> = result
+            .hypsometric_curve
+            .iter()
+            .map(|p| (p.elevation, p.area_above))
+            .collect();
+        assert_eq!(
+            vec![(1.0, 1.0), (10.0, 0.6), (20.0, 0.2), (25.0, 0.0)],
+            curve
+        );
+    }
+
+    #[test]
+    fn empty_dem_has_no_hypsometry() {
+        let dem = DEMRaster::new(1, 1, Origin::Corner(0.0, 0.0), 1.0, -9999.0, vec![-9999.0]);
+
+        assert!(hypsometry(&dem, 10.0, 0.0).is_none());
+    }
+}
@@ -1,15 +1,32 @@
+mod cache;
+mod contours;
+mod hillshade;
+mod hypsometry;
+mod mosaic;
+mod mounts;
 mod parser;
-mod raster;
+pub(crate) mod raster;
+mod ruggedness;
+mod smooth;
 
+use anyhow::bail;
 use flate2::bufread::GzDecoder;
 use std::{
-    fs::File,
+    fs::{self, File},
     io::{BufReader, Read},
     path::Path,
 };
 
+use cache::{read_cache, write_cache, SourceStamp};
+pub use contours::contour_lines;
+pub use hillshade::hillshade;
+pub use hypsometry::{hypsometry, ElevationDistribution};
+pub use mosaic::mosaic;
+pub use mounts::{find_mounts, relief, Mount};
 pub use parser::DEMParser;
 pub use raster::DEMRaster;
+pub use ruggedness::ruggedness_index;
+pub use smooth::{smooth, Smoothing};
 
 pub fn load_dem(path: &Path) -> anyhow::Result<DEMRaster> {
     let file = File::open(path)?;
@@ -26,3 +43,146 @@ pub fn load_dem(path: &Path) -> anyhow::Result<DEMRaster> {
 
     Ok(raster)
 }
+
+/// Like [`load_dem`], but reuses the binary cache at `cache_path` if it was
+/// built from the same file and (re)writes it otherwise.
+pub fn load_dem_cached(path: &Path, cache_path: &Path) -> anyhow::Result<DEMRaster> {
+    let stamp = SourceStamp::from_file(path)?;
+
+    match read_cache(cache_path, &stamp) {
+        Ok(Some(raster)) => return Ok(raster),
+        Ok(None) => {}
+        Err(e) => warning!("Ignoring invalid DEM cache: {}", e),
+    }
+
+    let raster = load_dem(path)?;
+
+    if let Err(e) = write_cache(cache_path, &raster, &stamp, true) {
+        warning!("Failed to write DEM cache: {}", e);
+    }
+
+    Ok(raster)
+}
+
+/// Loads the DEM of a grad_meh map directory. That's either `dem.asc.gz` or,
+/// for worlds which were exported in chunks, a mosaic of all `*.asc.gz` files
+/// within the `dem` directory (in alphabetical order, later files win where
+/// they overlap).
+pub fn load_map_dem(input_path: &Path, use_cache: bool) -> anyhow::Result<DEMRaster> {
+    let load = |path: &Path| {
+        if use_cache {
+            load_dem_cached(path, &path.with_extension("").with_extension("cache"))
+        } else {
+            load_dem(path)
+        }
+    };
+
+    let dem_path = input_path.join("dem.asc.gz");
+    if dem_path.is_file() {
+        return load(&dem_path);
+    }
+
+    let chunks_path = input_path.join("dem");
+    if !chunks_path.is_dir() {
+        bail!("Couldn't find dem.asc.gz");
+    }
+
+    let mut chunk_paths = Vec::new();
+    for entry in fs::read_dir(&chunks_path)? {
+        let path = entry?.path();
+        let is_chunk = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(".asc.gz"));
+        if is_chunk && path.is_file() {
+            chunk_paths.push(path);
+        }
+    }
+    chunk_paths.sort();
+
+    if chunk_paths.is_empty() {
+        bail!("Couldn't find dem.asc.gz or any *.asc.gz in the dem directory");
+    }
+
+    let chunks = chunk_paths
+        .iter()
+        .map(|path| load(path))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    mosaic(&chunks)
+}
+
+/// Warns if the DEM doesn't span the world (by more than one cell), which
+/// leaves it misaligned with everything else positioned in in-game meters.
+/// With `rescale` the cell size is adjusted so the DEM is as wide as the world.
+pub fn reconcile_world_size(dem: &mut DEMRaster, world_size: u32, rescale: bool) {
+    let (columns, rows) = dem.dimensions();
+    let cell_size = dem.cell_size();
+    let width = columns as f32 * cell_size;
+    let height = rows as f32 * cell_size;
+    let world_size = world_size as f32;
+
+    if (width - world_size).abs() <= cell_size && (height - world_size).abs() <= cell_size {
+        return;
+    }
+
+    if !rescale {
+        warning!(
+            "DEM spans {}m x {}m, but the world is {}m wide. Use --rescale-dem to scale it to the world size",
+            width,
+            height,
+            world_size
+        );
+        return;
+    }
+
+    let scaled_cell_size = world_size / columns as f32;
+    dem.set_cell_size(scaled_cell_size);
+    info!(
+        "Rescaled DEM cell size from {}m to {}m to match the world size of {}m",
+        cell_size, scaled_cell_size, world_size
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{raster::Origin, reconcile_world_size, DEMRaster};
+
+    fn dem(cell_size: f32) -> DEMRaster {
+        DEMRaster::new(
+            4,
+            4,
+            Origin::Corner(0.0, 0.0),
+            cell_size,
+            -9999.0,
+            vec![0.0; 16],
+        )
+    }
+
+    #[test]
+    fn keeps_dem_without_rescale() {
+        let mut raster = dem(10.0);
+
+        reconcile_world_size(&mut raster, 100, false);
+
+        assert_eq!(10.0, raster.cell_size());
+    }
+
+    #[test]
+    fn rescales_dem_to_world_size() {
+        let mut raster = dem(10.0);
+
+        reconcile_world_size(&mut raster, 100, true);
+
+        assert_eq!(25.0, raster.cell_size());
+    }
+
+    #[test]
+    fn tolerates_one_cell_difference() {
+        let mut raster = dem(10.0);
+
+        reconcile_world_size(&mut raster, 48, true);
+
+        assert_eq!(10.0, raster.cell_size());
+    }
+}
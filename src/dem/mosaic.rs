@@ -0,0 +1,124 @@
+use anyhow::bail;
+
+use super::{raster::Origin, DEMRaster};
+
+/// Maximum misalignment (as a fraction of the cell size) of a raster to the
+/// grid of the mosaic.
+const ALIGNMENT_TOLERANCE: f32 = 0.01;
+
+/// Combines rasters of sub-regions into one raster covering all of them.
+/// All rasters need the same cell size and have to lie on the same grid.
+/// Where rasters overlap, later rasters win unless they have no data there.
+/// Cells which aren't covered by any raster get the nodata value of the first raster.
+pub fn mosaic(rasters: &[DEMRaster]) -> anyhow::Result<DEMRaster> {
+    let first = match rasters.first() {
+        Some(first) => first,
+        None => bail!("Can't build a mosaic without any DEM"),
+    };
+    let cell_size = first.cell_size();
+    let no_data = first.no_data_value();
+
+    if rasters.iter().any(|r| r.cell_size() != cell_size) {
+        bail!("All DEMs of a mosaic need the same cell size");
+    }
+
+    let (first_left, first_bottom) = first.lower_left();
+    let (mut left, mut bottom, mut right, mut top) =
+        (first_left, first_bottom, first_left, first_bottom);
+    for raster in rasters {
+        let (r_left, r_bottom) = raster.lower_left();
+        let (columns, rows) = raster.dimensions();
+        left = left.min(r_left);
+        bottom = bottom.min(r_bottom);
+        right = right.max(r_left + columns as f32 * cell_size);
+        top = top.max(r_bottom + rows as f32 * cell_size);
+    }
+
+    let columns = ((right - left) / cell_size).round() as usize;
+    let rows = ((top - bottom) / cell_size).round() as usize;
+    let mut data = vec![no_data; columns * rows];
+
+    for raster in rasters {
+        let (r_left, r_bottom) = raster.lower_left();
+        let r_top = r_bottom + raster.dimensions().1 as f32 * cell_size;
+
+        let col_offset = grid_offset(r_left - left, cell_size)?;
+        let row_offset = grid_offset(top - r_top, cell_size)?;
+
+        for (col, row, _, _, z) in raster.iter_cells() {
+            if z == raster.no_data_value() {
+                continue;
+            }
+
+            data[(col_offset + col) + (row_offset + row) * columns] = z;
+        }
+    }
+
+    Ok(DEMRaster::new(
+        columns,
+        rows,
+        Origin::Corner(left, bottom),
+        cell_size,
+        no_data,
+        data,
+    ))
+}
+
+fn grid_offset(distance: f32, cell_size: f32) -> anyhow::Result<usize> {
+    let cells = distance / cell_size;
+
+    if (cells - cells.round()).abs() > ALIGNMENT_TOLERANCE {
+        bail!("DEMs of a mosaic have to be aligned to the same grid");
+    }
+
+    Ok(cells.round() as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mosaic;
+    use crate::dem::{raster::Origin, DEMRaster};
+
+    fn raster(left: f32, bottom: f32, data: Vec<f32>) -> DEMRaster {
+        DEMRaster::new(2, 2, Origin::Corner(left, bottom), 10.0, -9999.0, data)
+    }
+
+    #[test]
+    fn combines_neighboring_rasters() {
+        let west = raster(0.0, 0.0, vec![1.0, 2.0, 3.0, 4.0]);
+        let north_east = raster(20.0, 20.0, vec![5.0, 6.0, 7.0, 8.0]);
+
+        let result = mosaic(&[west, north_east]).unwrap();
+
+        assert_eq!((4, 4), result.dimensions());
+        assert_eq!((0.0, 0.0), result.lower_left());
+        assert_eq!(
+            &vec![
+                -9999.0, -9999.0, 5.0, 6.0, //
+                -9999.0, -9999.0, 7.0, 8.0, //
+                1.0, 2.0, -9999.0, -9999.0, //
+                3.0, 4.0, -9999.0, -9999.0,
+            ],
+            result.get_data()
+        );
+    }
+
+    #[test]
+    fn later_rasters_win_on_overlap_unless_nodata() {
+        let a = raster(0.0, 0.0, vec![1.0, 2.0, 3.0, 4.0]);
+        let b = raster(10.0, 0.0, vec![-9999.0, 6.0, 7.0, 8.0]);
+
+        let result = mosaic(&[a, b]).unwrap();
+
+        assert_eq!((3, 2), result.dimensions());
+        assert_eq!(&vec![1.0, 2.0, 6.0, 3.0, 7.0, 8.0], result.get_data());
+    }
+
+    #[test]
+    fn rejects_misaligned_rasters() {
+        let a = raster(0.0, 0.0, vec![0.0; 4]);
+        let b = raster(5.0, 0.0, vec![0.0; 4]);
+
+        assert!(mosaic(&[a, b]).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use super::DEMRaster;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mount {
+    pub x: f32,
+    pub y: f32,
+    pub elevation: f32,
+    /// how far one has to descend from the mount to reach higher terrain
+    pub prominence: f32,
+}
+
+/// Finds all peaks of `dem` with a topographic prominence of at least
+/// `min_prominence`, ordered by prominence. Cells within `radius` cells of
+/// each other count as neighbors, so a peak needs to be higher than all cells
+/// within this radius. The highest peak of every area
+/// surrounded by nodata counts as prominent down to the lowest elevation of
+/// the DEM.
+///
+/// Cells are added from the highest to the lowest, merging each with the
+/// areas of its neighbors added before. Once two areas meet, the lower of
+/// their peaks can't go any higher without descending to the merging cell,
+/// which makes the difference its prominence.
+pub fn find_mounts(dem: &DEMRaster, min_prominence: f32, radius: usize) -> Vec<Mount> {
+    let (columns, _) = dem.dimensions();
+    let no_data = dem.no_data_value();
+    let data = dem.get_data();
+
+    let mut order: Vec<usize> = (0..data.len()).filter(|i| data[*i] != no_data).collect();
+    order.sort_unstable_by(|a, b| data[*b].total_cmp(&data[*a]).then(a.cmp(b)));
+    let lowest = match order.last() {
+        Some(index) => data[*index],
+        None => return Vec::new(),
+    };
+    let mut rank = vec![0; data.len()];
+    for (position, index) in order.iter().enumerate() {
+        rank[*index] = position;
+    }
+
+    // union-find of the areas, with the peak of every area as its root
+    let mut parent: Vec<usize> = (0..data.len()).collect();
+    let mut added = vec![false; data.len()];
+    let mut prominence: Vec<Option<f32>> = vec![None; data.len()];
+
+    fn root(parent: &mut [usize], mut index: usize) -> usize {
+        while parent[index] != index {
+            parent[index] = parent[parent[index]];
+            index = parent[index];
+        }
+        index
+    }
+
+    for &index in &order {
+        added[index] = true;
+        let (col, row) = (index % columns, index / columns);
+
+        for (n_col, n_row, _) in dem.neighbors(col, row, radius) {
+            let neighbor = n_col + n_row * columns;
+            if !added[neighbor] {
+                continue;
+            }
+
+            let a = root(&mut parent, index);
+            let b = root(&mut parent, neighbor);
+            if a == b {
+                continue;
+            }
+
+            let (high, low) = if rank[a] < rank[b] { (a, b) } else { (b, a) };
+            prominence[low] = Some(data[low] - data[index]);
+            parent[low] = high;
+        }
+    }
+
+    for index in 0..data.len() {
+        if data[index] != no_data && root(&mut parent, index) == index {
+            prominence[index] = Some(data[index] - lowest);
+        }
+    }
+
+    let mut mounts: Vec<Mount> = prominence
+        .iter()
+        .enumerate()
+        .filter_map(|(index, prominence)| {
+            let prominence = (*prominence)?;
+            if prominence <= 0.0 || prominence < min_prominence {
+                return None;
+            }
+
+            let (col, row) = (index % columns, index / columns);
+            Some(Mount {
+                x: dem.x(col),
+                y: dem.y(row),
+                elevation: data[index],
+                prominence,
+            })
+        })
+        .collect();
+    mounts.sort_by(|a, b| b.prominence.total_cmp(&a.prominence));
+
+    mounts
+}
+
+/// Height of `mount` above the mean elevation of the cells within `radius`
+/// cells around it, leaving out nodata cells.
+pub fn relief(dem: &DEMRaster, mount: &Mount, radius: usize) -> f32 {
+    let (left, bottom) = dem.lower_left();
+    let (_, rows) = dem.dimensions();
+    let col = ((mount.x - left) / dem.cell_size()).round() as usize;
+    let row = rows - ((mount.y - bottom) / dem.cell_size()).round() as usize;
+    let no_data = dem.no_data_value();
+
+    let (sum, count) = dem
+        .neighbors(col, row, radius)
+        .filter(|(_, _, z)| *z != no_data)
+        .fold((0.0, 0), |(sum, count), (_, _, z)| (sum + z, count + 1));
+
+    if count == 0 {
+        return 0.0;
+    }
+    mount.elevation - sum / count as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_mounts, relief};
+    use crate::dem::raster::{DEMRaster, Origin};
+
+    fn ridge(data: Vec<f32>) -> DEMRaster {
+        DEMRaster::new(data.len(), 1, Origin::Corner(0.0, 0.0), 1.0, -9999.0, data)
+    }
+
+    #[test]
+    fn measures_prominence_from_saddles() {
+        let mounts = find_mounts(&ridge(vec![0.0, 10.0, 4.0, 7.0, 5.0, 6.0, 0.0]), 0.0, 1);
+
+        let found: Vec<_> = mounts
+            .iter()
+            .map(|m| (m.x, m.elevation, m.prominence))
+            .collect();
+        assert_eq!(
+            vec![(1.0, 10.0, 10.0), (3.0, 7.0, 3.0), (5.0, 6.0, 1.0)],
+            found
+        );
+    }
+
+    #[test]
+    fn filters_by_prominence() {
+        let dem = ridge(vec![0.0, 10.0, 4.0, 7.0, 5.0, 6.0, 0.0]);
+
+        assert_eq!(2, find_mounts(&dem, 2.0, 1).len());
+        assert_eq!(1, find_mounts(&dem, 5.0, 1).len());
+    }
+
+    #[test]
+    fn merges_peaks_within_radius() {
+        let dem = ridge(vec![0.0, 10.0, 4.0, 7.0, 5.0, 6.0, 0.0]);
+
+        let found: Vec<_> = find_mounts(&dem, 0.0, 2)
+            .iter()
+            .map(|m| (m.elevation, m.prominence))
+            .collect();
+        assert_eq!(vec![(10.0, 10.0)], found);
+    }
+
+    #[test]
+    fn measures_relief_against_surrounding_mean() {
+        let dem = ridge(vec![0.0, 10.0, 4.0, 7.0, 5.0, 6.0, 0.0]);
+        let mounts = find_mounts(&dem, 0.0, 1);
+
+        assert_eq!(8.0, relief(&dem, &mounts[0], 1));
+        assert_eq!(2.5, relief(&dem, &mounts[1], 1));
+        assert!((relief(&dem, &mounts[1], 3) - (7.0 - 25.0 / 6.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn treats_nodata_as_barrier() {
+        let mounts = find_mounts(&ridge(vec![0.0, 10.0, -9999.0, 7.0, 1.0]), 0.0, 1);
+
+        let found: Vec<_> = mounts.iter().map(|m| (m.elevation, m.prominence)).collect();
+        assert_eq!(vec![(10.0, 10.0), (7.0, 7.0)], found);
+    }
+}
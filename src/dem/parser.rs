@@ -10,6 +10,8 @@ use nom::{
     AsChar, Compare, IResult, InputLength, InputTake, InputTakeAtPosition, Parser,
 };
 
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
 use super::{raster::Origin, DEMRaster};
 
 // #[derive(Debug, PartialEq)]
@@ -54,9 +56,9 @@ impl<I> ParseError<I> for DEMParserError {
     }
 }
 
-impl Into<nom::Err<DEMParserError>> for DEMParserError {
-    fn into(self) -> nom::Err<DEMParserError> {
-        nom::Err::Failure(self)
+impl From<DEMParserError> for nom::Err<DEMParserError> {
+    fn from(val: DEMParserError) -> Self {
+        nom::Err::Failure(val)
     }
 }
 
@@ -257,10 +259,9 @@ impl DEMParser {
             return Err(DEMParserError::MissingOrigin.into());
         }
 
-        let origin = if x_center.is_some() && y_center.is_some() {
-            Origin::Center(x_center.unwrap(), y_center.unwrap())
-        } else {
-            Origin::Corner(x_corner.unwrap(), y_corner.unwrap())
+        let origin = match (x_center, y_center) {
+            (Some(x), Some(y)) => Origin::Center(x, y),
+            _ => Origin::Corner(x_corner.unwrap(), y_corner.unwrap()),
         };
 
         Ok((
@@ -276,30 +277,32 @@ impl DEMParser {
     }
 
     pub fn parse(i: &str) -> Result<DEMRaster, DEMParserError> {
-        let mut input = i;
-        let (remaining_input, (columns, rows, origin, cell_size, no_data_value)) =
-            DEMParser::header(input)?;
-        input = remaining_input;
-
-        let mut data: Vec<f32> = Vec::with_capacity(columns * rows);
+        let (input, (columns, rows, origin, cell_size, no_data_value)) = DEMParser::header(i)?;
 
-        for row_index in 0..rows {
-            if input.len() == 0 {
-                return Err(DEMParserError::MissingRow.into());
-            }
+        // rows are independent of each other, so they are parsed in parallel
+        let lines: Vec<&str> = input.split_inclusive('\n').take(rows).collect();
+        if lines.len() < rows {
+            return Err(DEMParserError::MissingRow);
+        }
 
-            let (remaining_input, ref mut vec) = DEMParser::data_line(input)?;
-            input = remaining_input;
+        let parsed_rows: Vec<Result<Vec<f32>, DEMParserError>> = lines
+            .par_iter()
+            .enumerate()
+            .map(|(row_index, line)| {
+                let (_, mut vec) = DEMParser::data_line(line)?;
 
-            if vec.len() < columns {
-                return Err(DEMParserError::RowTooShort(row_index).into());
-            }
+                if vec.len() < columns {
+                    return Err(DEMParserError::RowTooShort(row_index));
+                }
 
-            if vec.len() > columns {
-                vec.drain(columns..);
-            }
+                vec.truncate(columns);
+                Ok(vec)
+            })
+            .collect();
 
-            data.append(vec);
+        let mut data: Vec<f32> = Vec::with_capacity(columns * rows);
+        for row in parsed_rows {
+            data.append(&mut row?);
         }
 
         Ok(DEMRaster::new(
@@ -312,3 +315,30 @@ impl DEMParser {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{DEMParser, DEMParserError};
+
+    const HEADER: &str = "ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 10\n";
+
+    #[test]
+    fn parses_rows_in_order() {
+        let raster = DEMParser::parse(&format!("{}1 2 3 9\r\n4 5 6\n", HEADER)).unwrap();
+
+        assert_eq!((3, 2), raster.dimensions());
+        assert_eq!(&vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], raster.get_data());
+    }
+
+    #[test]
+    fn reports_broken_rows() {
+        assert!(matches!(
+            DEMParser::parse(&format!("{}1 2 3\n4 5\n", HEADER)),
+            Err(DEMParserError::RowTooShort(1))
+        ));
+        assert!(matches!(
+            DEMParser::parse(&format!("{}1 2 3\n", HEADER)),
+            Err(DEMParserError::MissingRow)
+        ));
+    }
+}
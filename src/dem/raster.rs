@@ -1,10 +1,10 @@
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Origin {
     Center(f32, f32),
     Corner(f32, f32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct DEMRaster {
     columns: usize,
     rows: usize,
@@ -47,6 +47,23 @@ impl DEMRaster {
         (self.columns, self.rows)
     }
 
+    pub fn lower_left(&self) -> (f32, f32) {
+        (self.left, self.bottom)
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /// Changes the cell size, keeping the lower left corner in place.
+    pub fn set_cell_size(&mut self, cell_size: f32) {
+        self.cell_size = cell_size;
+    }
+
+    pub fn no_data_value(&self) -> f32 {
+        self.no_data_value
+    }
+
     pub fn x(&self, column: usize) -> f32 {
         self.left + column as f32 * self.cell_size
     }
@@ -60,7 +77,143 @@ impl DEMRaster {
         self.data[col + row * self.columns]
     }
 
+    /// Elevation of the cell at (`col`, `row`). Returns `None` outside of the
+    /// raster and for nodata cells.
+    pub fn try_z(&self, col: usize, row: usize) -> Option<f32> {
+        if col >= self.columns || row >= self.rows {
+            return None;
+        }
+
+        let z = self.z(col, row);
+        (z != self.no_data_value).then_some(z)
+    }
+
     pub fn get_data(&self) -> &Vec<f32> {
         &self.data
     }
+
+    /// Bilinearly interpolated elevation at the world position (`x`, `y`).
+    /// Positions between the outermost cells and the edge of the raster use
+    /// the outermost cells. Returns `None` outside of the raster or next to
+    /// nodata cells.
+    pub fn sample(&self, x: f32, y: f32) -> Option<f32> {
+        let fx = (x - self.left) / self.cell_size;
+        let fy = self.rows as f32 - (y - self.bottom) / self.cell_size;
+
+        if self.columns == 0
+            || self.rows == 0
+            || !(0.0..=self.columns as f32).contains(&fx)
+            || !(0.0..=self.rows as f32).contains(&fy)
+        {
+            return None;
+        }
+
+        let fx = fx.min((self.columns - 1) as f32);
+        let fy = fy.min((self.rows - 1) as f32);
+
+        let col = (fx.floor() as usize).min(self.columns.saturating_sub(2));
+        let row = (fy.floor() as usize).min(self.rows.saturating_sub(2));
+        let next_col = (col + 1).min(self.columns - 1);
+        let next_row = (row + 1).min(self.rows - 1);
+        let tx = fx - col as f32;
+        let ty = fy - row as f32;
+
+        let corners = [
+            self.try_z(col, row)?,
+            self.try_z(next_col, row)?,
+            self.try_z(col, next_row)?,
+            self.try_z(next_col, next_row)?,
+        ];
+
+        let top = corners[0] + (corners[1] - corners[0]) * tx;
+        let bottom = corners[2] + (corners[3] - corners[2]) * tx;
+
+        Some(top + (bottom - top) * ty)
+    }
+
+    /// Iterates over all cells row by row as `(col, row, x, y, z)`.
+    pub fn iter_cells(&self) -> impl Iterator<Item = (usize, usize, f32, f32, f32)> + '_ {
+        self.data.iter().enumerate().map(move |(index, z)| {
+            let col = index % self.columns;
+            let row = index / self.columns;
+            (col, row, self.x(col), self.y(row), *z)
+        })
+    }
+
+    /// Iterates over all cells within `radius` cells of (`col`, `row`) as
+    /// `(col, row, z)`, excluding the cell itself. The window is clipped at
+    /// the borders of the raster.
+    pub fn neighbors(
+        &self,
+        col: usize,
+        row: usize,
+        radius: usize,
+    ) -> impl Iterator<Item = (usize, usize, f32)> + '_ {
+        let cols = col.saturating_sub(radius)..(col + radius + 1).min(self.columns);
+        let rows = row.saturating_sub(radius)..(row + radius + 1).min(self.rows);
+
+        rows.flat_map(move |n_row| cols.clone().map(move |n_col| (n_col, n_row)))
+            .filter(move |&(n_col, n_row)| n_col != col || n_row != row)
+            .map(move |(n_col, n_row)| (n_col, n_row, self.z(n_col, n_row)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DEMRaster, Origin};
+
+    fn raster() -> DEMRaster {
+        DEMRaster::new(
+            3,
+            2,
+            Origin::Corner(10.0, 20.0),
+            2.0,
+            -9999.0,
+            vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0],
+        )
+    }
+
+    #[test]
+    fn iterates_cells() {
+        let cells: Vec<_> = raster().iter_cells().collect();
+
+        assert_eq!(6, cells.len());
+        assert_eq!((0, 0, 10.0, 24.0, 0.0), cells[0]);
+        assert_eq!((2, 1, 14.0, 22.0, 5.0), cells[5]);
+    }
+
+    #[test]
+    fn checks_bounds_and_nodata() {
+        let mut dem = raster();
+        dem.data[4] = -9999.0;
+
+        assert_eq!(Some(5.0), dem.try_z(2, 1));
+        assert_eq!(None, dem.try_z(1, 1));
+        assert_eq!(None, dem.try_z(3, 0));
+        assert_eq!(None, dem.try_z(0, 2));
+    }
+
+    #[test]
+    fn interpolates_samples() {
+        let dem = raster();
+
+        assert_eq!(Some(0.0), dem.sample(10.0, 24.0));
+        assert_eq!(Some(5.0), dem.sample(14.0, 22.0));
+        assert_eq!(Some(2.0), dem.sample(11.0, 23.0));
+        // between the last cells and the edge of the raster
+        assert_eq!(Some(5.0), dem.sample(16.0, 20.0));
+        assert_eq!(None, dem.sample(9.0, 23.0));
+        assert_eq!(None, dem.sample(11.0, 19.0));
+    }
+
+    #[test]
+    fn clips_neighbors_at_border() {
+        let dem = raster();
+
+        let corner: Vec<_> = dem.neighbors(0, 0, 1).collect();
+        assert_eq!(vec![(1, 0, 1.0), (0, 1, 3.0), (1, 1, 4.0)], corner);
+
+        assert_eq!(5, dem.neighbors(1, 1, 1).count());
+        assert_eq!(5, dem.neighbors(1, 1, 5).count());
+    }
 }
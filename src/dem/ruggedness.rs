@@ -0,0 +1,75 @@
+use super::DEMRaster;
+
+/// Terrain Ruggedness Index (Riley et al.) of every cell: the root of the
+/// summed squared elevation differences to the eight neighbors. Cells at the
+/// border or next to nodata cells extrapolate from the neighbors they have.
+/// Nodata cells yield `NaN`.
+pub fn ruggedness_index(dem: &DEMRaster) -> Vec<f32> {
+    let (columns, rows) = dem.dimensions();
+    let no_data = dem.no_data_value();
+
+    let mut tri = Vec::with_capacity(columns * rows);
+
+    for (col, row, _, _, z) in dem.iter_cells() {
+        if z == no_data {
+            tri.push(f32::NAN);
+            continue;
+        }
+
+        let mut sum = 0.0;
+        let mut count = 0;
+
+        for (_, _, n_z) in dem.neighbors(col, row, 1) {
+            if n_z == no_data {
+                continue;
+            }
+
+            sum += (n_z - z).powi(2);
+            count += 1;
+        }
+
+        tri.push(if count == 0 {
+            0.0
+        } else {
+            (sum * 8.0 / count as f32).sqrt()
+        });
+    }
+
+    tri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ruggedness_index;
+    use crate::dem::{raster::Origin, DEMRaster};
+
+    #[test]
+    fn flat_terrain_is_zero_and_nodata_is_nan() {
+        let dem = DEMRaster::new(
+            3,
+            2,
+            Origin::Corner(0.0, 0.0),
+            1.0,
+            -9999.0,
+            vec![5.0, 5.0, 5.0, 5.0, 5.0, -9999.0],
+        );
+
+        let tri = ruggedness_index(&dem);
+
+        assert_eq!(&[0.0; 5], &tri[..5]);
+        assert!(tri[5].is_nan());
+    }
+
+    #[test]
+    fn single_spike() {
+        let mut data = vec![0.0; 9];
+        data[4] = 1.0;
+        let dem = DEMRaster::new(3, 3, Origin::Corner(0.0, 0.0), 1.0, -9999.0, data);
+
+        let tri = ruggedness_index(&dem);
+
+        assert!((tri[4] - 8f32.sqrt()).abs() < 1e-6);
+        // corner cell has three neighbors, one of them is one meter higher
+        assert!((tri[0] - (8.0f32 / 3.0).sqrt()).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,109 @@
+use rayon::{
+    iter::{IndexedParallelIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+use super::{raster::Origin, DEMRaster};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoothing {
+    /// Weighted mean with a standard deviation of half the radius
+    Gaussian,
+    Median,
+}
+
+/// Copy of `dem` with every cell replaced by the filtered elevations of the
+/// cells within `radius` cells of it. Nodata cells are left out of the window
+/// and stay nodata.
+pub fn smooth(dem: &DEMRaster, smoothing: Smoothing, radius: usize) -> DEMRaster {
+    let (columns, rows) = dem.dimensions();
+    let no_data = dem.no_data_value();
+    let sigma = (radius as f32 / 2.0).max(0.5);
+
+    let mut data = vec![no_data; columns * rows];
+    data.par_chunks_mut(columns.max(1))
+        .enumerate()
+        .for_each(|(row, cells)| {
+            // reused for the median of every cell of the row
+            let mut values = Vec::with_capacity((2 * radius + 1).pow(2));
+
+            for (col, cell) in cells.iter_mut().enumerate() {
+                let z = dem.z(col, row);
+                if z == no_data {
+                    continue;
+                }
+
+                let window = dem
+                    .neighbors(col, row, radius)
+                    .chain(std::iter::once((col, row, z)))
+                    .filter(|(_, _, z)| *z != no_data);
+
+                *cell = match smoothing {
+                    Smoothing::Gaussian => {
+                        let (sum, weights) =
+                            window.fold((0.0, 0.0), |(sum, weights), (c, r, z)| {
+                                let dc = c as f32 - col as f32;
+                                let dr = r as f32 - row as f32;
+                                let weight = (-(dc * dc + dr * dr) / (2.0 * sigma * sigma)).exp();
+                                (sum + z * weight, weights + weight)
+                            });
+                        sum / weights
+                    }
+                    Smoothing::Median => {
+                        values.clear();
+                        values.extend(window.map(|(_, _, z)| z));
+                        values.sort_unstable_by(f32::total_cmp);
+                        let middle = values.len() / 2;
+                        if values.len().is_multiple_of(2) {
+                            (values[middle - 1] + values[middle]) / 2.0
+                        } else {
+                            values[middle]
+                        }
+                    }
+                };
+            }
+        });
+
+    let (left, bottom) = dem.lower_left();
+    DEMRaster::new(
+        columns,
+        rows,
+        Origin::Corner(left, bottom),
+        dem.cell_size(),
+        no_data,
+        data,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{smooth, Smoothing};
+    use crate::dem::raster::{DEMRaster, Origin};
+
+    fn spike() -> DEMRaster {
+        let mut data = vec![0.0; 9];
+        data[4] = 9.0;
+        data[8] = -9999.0;
+
+        DEMRaster::new(3, 3, Origin::Corner(0.0, 0.0), 1.0, -9999.0, data)
+    }
+
+    #[test]
+    fn median_removes_spikes() {
+        let smoothed = smooth(&spike(), Smoothing::Median, 1);
+
+        assert_eq!(0.0, smoothed.z(1, 1));
+        assert_eq!(-9999.0, smoothed.z(2, 2));
+        assert_eq!(spike().lower_left(), smoothed.lower_left());
+    }
+
+    #[test]
+    fn gaussian_spreads_spikes() {
+        let smoothed = smooth(&spike(), Smoothing::Gaussian, 1);
+
+        let center = smoothed.z(1, 1);
+        let side = smoothed.z(1, 0);
+        assert!(center < 9.0 && center > side && side > 0.0);
+        assert_eq!(-9999.0, smoothed.z(2, 2));
+    }
+}
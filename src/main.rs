@@ -1,18 +1,27 @@
-use clap::{app_from_crate, AppSettings};
+use clap::{app_from_crate, arg, App, AppSettings};
+use clap_complete::Shell;
 use std::collections::HashMap;
+use std::path::Path;
 use commands::Command;
 
+#[macro_use]
+mod output;
+
 mod commands;
+mod crs;
 mod dem;
+mod manifest;
 mod metajson;
 mod tilejson;
+mod trace;
 mod utils;
+mod worldfile;
 
 fn main() {
     let args: Vec<_> = std::env::args().collect();
 
     if let Err(e) = execute(&args) {
-        println!("❌ Error: {}", e);
+        error!("{}", e);
         std::process::exit(1);
     }
 }
@@ -21,13 +30,26 @@ fn execute(input: &[String]) -> anyhow::Result<()> {
     let mut app = app_from_crate!()
         .global_setting(AppSettings::PropagateVersion)
         .global_setting(AppSettings::UseLongFormatForHelpSubcommand)
-        .setting(AppSettings::SubcommandRequiredElseHelp);
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(arg!(--plain "ASCII-only output without emoji, one line per message").global(true))
+        .arg(arg!(--trace <FILE> "Write a Chrome trace of the run to this file").required(false).global(true))
+        .arg(arg!(--"trace-tiles" "Also trace every single tile (makes the trace huge)").global(true));
 
     let mut commands_by_name: HashMap<String, &dyn Command> = HashMap::new();
     let commands: Vec<&dyn Command> = vec![
         &commands::Preview {},
         &commands::Sat {},
         &commands::TerrainRGB {},
+        &commands::Ruggedness {},
+        &commands::Tint {},
+        &commands::Prune {},
+        &commands::Profile {},
+        &commands::Hypsometry {},
+        &commands::Contours {},
+        &commands::Mounts {},
+        &commands::ElevationQuery {},
+        &commands::Serve {},
+        &commands::All {},
         // Add commands here
     ];
 
@@ -36,12 +58,37 @@ fn execute(input: &[String]) -> anyhow::Result<()> {
         commands_by_name.insert(sub.get_name().to_owned(), *command);
         app = app.subcommand(sub);
     }
+    app = app.subcommand(
+        App::new("completions")
+            .about("Print a completion script for the given shell.")
+            .arg(
+                arg!(<SHELL> "Shell to complete in")
+                    .possible_values(["bash", "zsh", "fish", "powershell", "elvish"]),
+            ),
+    );
 
-    let matches = app.get_matches_from(input);
+    let matches = app.clone().get_matches_from(input);
 
     let result = match matches.subcommand() {
+        Some(("completions", sub_matches)) => {
+            let shell = sub_matches
+                .value_of("SHELL")
+                .unwrap()
+                .parse::<Shell>()
+                .map_err(anyhow::Error::msg)?;
+            let name = env!("CARGO_PKG_NAME");
+            clap_complete::generate(shell, &mut app, name, &mut std::io::stdout());
+            Ok(())
+        }
         Some((name, sub_matches)) => match commands_by_name.get(name) {
-            Some(command) => command.run(sub_matches),
+            Some(command) => {
+                crate::output::set_plain(sub_matches.is_present("plain"));
+                let _trace = sub_matches.value_of("trace").map(|path| {
+                    crate::trace::init(Path::new(path), sub_matches.is_present("trace-tiles"))
+                }).transpose()?;
+                let _span = tracing::info_span!("command", name).entered();
+                command.run(sub_matches)
+            }
             _ => unreachable!(),
         },
         _ => unreachable!(),
@@ -0,0 +1,214 @@
+use anyhow::bail;
+use serde_json::{json, to_string_pretty, Map, Value};
+
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::metajson::MetaJSON;
+
+/*
+    map.json is the one discovery document of a map. Every command which
+    is run with --manifest adds its output to it, all paths are relative to
+    the directory of map.json:
+
+    {
+        "worldName": "altis",
+        ...
+        "tileSets": { "sat": "sat/tile.json", "terrain_rgb": "terrain/tile.json" },
+        "preview": ["preview/preview.png", "preview/preview_256.png"]
+    }
+*/
+
+/// Adds the tile.json of the tile set in `tile_set_dir` as `name` to the manifest.
+pub fn add_tile_set(
+    manifest_path: &Path,
+    meta: &MetaJSON,
+    name: &str,
+    tile_set_dir: &Path,
+) -> anyhow::Result<()> {
+    update(manifest_path, meta, |manifest| {
+        let tile_json = relative_path(
+            &manifest_dir(manifest_path)?,
+            &tile_set_dir.join("tile.json"),
+        )?;
+
+        let tile_sets = match manifest
+            .entry("tileSets")
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+        {
+            Some(tile_sets) => tile_sets,
+            None => bail!("{} has an invalid tileSets entry", manifest_path.display()),
+        };
+        tile_sets.insert(String::from(name), tile_json.into());
+
+        Ok(())
+    })
+}
+
+/// Lists all images in `preview_dir` as the preview assets of the manifest.
+pub fn add_preview(
+    manifest_path: &Path,
+    meta: &MetaJSON,
+    preview_dir: &Path,
+) -> anyhow::Result<()> {
+    update(manifest_path, meta, |manifest| {
+        let mut images = Vec::new();
+        for entry in fs::read_dir(preview_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "png") {
+                images.push(relative_path(&manifest_dir(manifest_path)?, &path)?);
+            }
+        }
+        images.sort();
+
+        manifest.insert(String::from("preview"), images.into());
+
+        Ok(())
+    })
+}
+
+fn update(
+    manifest_path: &Path,
+    meta: &MetaJSON,
+    f: impl FnOnce(&mut Map<String, Value>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut manifest = match manifest_path.is_file() {
+        true => match serde_json::from_str(&fs::read_to_string(manifest_path)?)? {
+            Value::Object(manifest) => manifest,
+            _ => Map::new(),
+        },
+        false => Map::new(),
+    };
+
+    let meta_info = json!({
+        "worldName": meta.world_name,
+        "displayName": meta.display_name,
+        "author": meta.author,
+        "worldSize": meta.world_size,
+        "elevationOffset": meta.elevation_offset,
+        "latitude": meta.latitude,
+        "longitude": meta.longitude,
+    });
+    if let Value::Object(meta_info) = meta_info {
+        manifest.extend(meta_info);
+    }
+
+    f(&mut manifest)?;
+
+    fs::write(manifest_path, to_string_pretty(&manifest)?)?;
+
+    Ok(())
+}
+
+fn manifest_dir(manifest_path: &Path) -> anyhow::Result<PathBuf> {
+    let dir = match manifest_path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+
+    Ok(fs::canonicalize(dir)?)
+}
+
+/// Path of `to` relative to the directory `from` with forward slashes.
+fn relative_path(from: &Path, to: &Path) -> anyhow::Result<String> {
+    let to = match to.parent() {
+        Some(parent) => fs::canonicalize(parent)?.join(to.file_name().unwrap_or_default()),
+        None => to.to_owned(),
+    };
+
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let parts: Vec<String> = from[common..]
+        .iter()
+        .map(|_| String::from(".."))
+        .chain(to[common..].iter().filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        }))
+        .collect();
+
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_preview, add_tile_set, relative_path};
+    use crate::metajson::MetaJSON;
+    use std::fs;
+    use tempdir::TempDir;
+
+    fn meta() -> MetaJSON {
+        serde_json::from_str(
+            r#"{
+                "author": "me",
+                "displayName": "Test",
+                "elevationOffset": 5,
+                "gridOffsetX": 0,
+                "gridOffsetY": 0,
+                "grids": [],
+                "latitude": 0,
+                "longitude": 0,
+                "version": 0.1,
+                "worldName": "test",
+                "worldSize": 2048
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn builds_relative_paths() {
+        let dir = TempDir::new("meh-utils-rust-manifest").unwrap();
+        let base = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(base.join("a/b")).unwrap();
+        fs::create_dir_all(base.join("c")).unwrap();
+
+        assert_eq!(
+            "b/tile.json",
+            relative_path(&base.join("a"), &base.join("a/b/tile.json")).unwrap()
+        );
+        assert_eq!(
+            "../a/b/tile.json",
+            relative_path(&base.join("c"), &base.join("a/b/tile.json")).unwrap()
+        );
+    }
+
+    #[test]
+    fn merges_entries_of_multiple_commands() {
+        let dir = TempDir::new("meh-utils-rust-manifest").unwrap();
+        let base = dir.path();
+        fs::create_dir_all(base.join("sat")).unwrap();
+        fs::create_dir_all(base.join("preview")).unwrap();
+        fs::write(base.join("preview/preview.png"), "").unwrap();
+        fs::write(base.join("preview/preview.jgw"), "").unwrap();
+        let manifest_path = base.join("map.json");
+
+        add_tile_set(&manifest_path, &meta(), "sat", &base.join("sat")).unwrap();
+        add_preview(&manifest_path, &meta(), &base.join("preview")).unwrap();
+
+        let manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+        assert_eq!("test", manifest["worldName"]);
+        assert_eq!("sat/tile.json", manifest["tileSets"]["sat"]);
+        assert_eq!(
+            serde_json::json!(["preview/preview.png"]),
+            manifest["preview"]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_tile_sets() {
+        let dir = TempDir::new("meh-utils-rust-manifest").unwrap();
+        let base = dir.path();
+        fs::create_dir_all(base.join("sat")).unwrap();
+        let manifest_path = base.join("map.json");
+        fs::write(&manifest_path, r#"{ "tileSets": [] }"#).unwrap();
+
+        assert!(add_tile_set(&manifest_path, &meta(), "sat", &base.join("sat")).is_err());
+    }
+}
@@ -2,6 +2,7 @@ use std::fs::File;
 use std::io::{BufReader, Error, ErrorKind};
 use std::path::Path;
 
+use image::Rgba;
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -34,6 +35,14 @@ pub struct MetaJSON {
     pub world_size: u32,
 }
 
+impl MetaJSON {
+    /// colorOutside as 8-bit RGBA.
+    pub fn color_outside_rgba(&self) -> Option<Rgba<u8>> {
+        self.color_outside
+            .map(|color| Rgba(color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)))
+    }
+}
+
 pub fn from_file(path: &Path) -> Result<MetaJSON, Box<Error>> {
     if !path.is_file() {
         return Err(Box::new(Error::new(
@@ -47,6 +56,6 @@ pub fn from_file(path: &Path) -> Result<MetaJSON, Box<Error>> {
 
     match serde_json::from_reader(reader) {
         Ok(meta) => Ok(meta),
-        Err(err) => Err(Box::new(Error::new(ErrorKind::Other, err.to_string()))),
+        Err(err) => Err(Box::new(Error::other(err.to_string()))),
     }
 }
@@ -0,0 +1,154 @@
+use std::fmt::Arguments;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
+
+static PLAIN: AtomicBool = AtomicBool::new(false);
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+static STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Switches all output to ASCII-only, single-line messages tagged with INFO/WARN/ERROR.
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+}
+
+/// Sends all messages to stderr, for commands which print their result to stdout.
+pub fn set_stderr(stderr: bool) {
+    STDERR.store(stderr, Ordering::Relaxed);
+}
+
+fn write_line(line: &str) {
+    if STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Status {
+    Start,
+    Done,
+    /// step of a larger task
+    SubDone,
+    Info,
+    Warning,
+    Error,
+}
+
+pub fn print(status: Status, message: Arguments) {
+    match status {
+        Status::Start => crate::trace::start_stage(&message.to_string()),
+        Status::Done => crate::trace::end_stage(),
+        Status::Warning => {
+            WARNINGS.fetch_add(1, Ordering::Relaxed);
+        }
+        _ => {}
+    }
+
+    if !PLAIN.load(Ordering::Relaxed) {
+        let prefix = match status {
+            Status::Start => "▶️  ",
+            Status::Done => "✔️  ",
+            Status::SubDone => "    ✔️  ",
+            Status::Info => "ℹ️  ",
+            Status::Warning => "⚠️  ",
+            Status::Error => "❌  ",
+        };
+        write_line(&format!("{}{}", prefix, message));
+        return;
+    }
+
+    let tag = match status {
+        Status::Warning => "WARN",
+        Status::Error => "ERROR",
+        _ => "INFO",
+    };
+    write_line(&format!("{}: {}", tag, plain_message(&message.to_string())));
+}
+
+/// Prints the closing line of a command, including how many warnings were
+/// printed, so they aren't missed among the other output.
+pub fn finished(start: Instant) {
+    let warnings = match WARNINGS.load(Ordering::Relaxed) {
+        0 => String::new(),
+        1 => " with 1 warning".to_owned(),
+        n => format!(" with {} warnings", n),
+    };
+
+    if PLAIN.load(Ordering::Relaxed) {
+        write_line(&format!(
+            "INFO: Finished in {}ms{}",
+            start.elapsed().as_millis(),
+            warnings
+        ));
+    } else {
+        write_line(&format!(
+            "\n    🎉  Finished in {}ms{}",
+            start.elapsed().as_millis(),
+            warnings
+        ));
+    }
+}
+
+/// Squashes a message into a single ASCII-only line.
+fn plain_message(message: &str) -> String {
+    message
+        .split(['\n', '\r'])
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("; ")
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '?' })
+        .collect()
+}
+
+macro_rules! step {
+    ($($arg:tt)*) => {
+        $crate::output::print($crate::output::Status::Start, format_args!($($arg)*))
+    };
+}
+
+macro_rules! done {
+    ($($arg:tt)*) => {
+        $crate::output::print($crate::output::Status::Done, format_args!($($arg)*))
+    };
+}
+
+macro_rules! sub_done {
+    ($($arg:tt)*) => {
+        $crate::output::print($crate::output::Status::SubDone, format_args!($($arg)*))
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::output::print($crate::output::Status::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::output::print($crate::output::Status::Warning, format_args!($($arg)*))
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::output::print($crate::output::Status::Error, format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::plain_message;
+
+    #[test]
+    fn squashes_messages_to_one_ascii_line() {
+        assert_eq!(
+            "Failed to load tiles:; a.png; b.png",
+            plain_message("Failed to load tiles:\n\ta.png\n\tb.png\n")
+        );
+        assert_eq!("Elevation in ?m", plain_message("Elevation in µm"));
+    }
+}
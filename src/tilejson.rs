@@ -1,11 +1,12 @@
 use serde::Serialize;
 
-use std::{collections::HashMap, fs::File, path::Path};
+use std::{collections::HashMap, fs, fs::File, path::Path};
 
-use serde_json::to_string_pretty;
+use serde_json::{to_string_pretty, Value};
 
 use std::io::{Error, Write};
 
+use crate::crs::CRS;
 use crate::metajson::MetaJSON;
 
 #[derive(Debug, Serialize)]
@@ -29,22 +30,26 @@ pub struct TileJSON {
 
     #[serde(rename = "snake_case")]
     pub vector_layers: Option<Vec<TileJSONLayer>>,
+
+    pub crs: CRS,
 }
 
 pub fn write(
     dir: &Path,
+    min_lod: u8,
     max_lod: u8,
-    meta: MetaJSON,
+    meta: &MetaJSON,
+    crs: CRS,
     type_display_name: &str,
     vector_layer_names: Vec<String>,
 ) -> Result<(), Error> {
     let vector_layers: Vec<_> = vector_layer_names
         .iter()
         .map(|name| -> TileJSONLayer {
-            return TileJSONLayer {
+            TileJSONLayer {
                 id: name.clone(),
                 fields: layer_fields(name),
-            };
+            }
         })
         .collect();
 
@@ -56,9 +61,10 @@ pub fn write(
             type_display_name, meta.display_name, meta.author
         ),
         scheme: String::from("xyz"),
-        min_zoom: 0,
+        min_zoom: min_lod,
         max_zoom: max_lod,
         vector_layers: Some(vector_layers),
+        crs,
     };
 
     let mut file = File::create(dir.join("tile.json"))?;
@@ -67,6 +73,110 @@ pub fn write(
     file.write_all(json.as_bytes())
 }
 
+/// Widens `min_lod`..`max_lod` to the zoom range of an existing tile.json in
+/// `dir`, so rebuilding some LODs of a tile set keeps the others listed.
+pub fn merge_zoom_range(dir: &Path, min_lod: u8, max_lod: u8) -> (u8, u8) {
+    let tile_json: Option<Value> = fs::read_to_string(dir.join("tile.json"))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok());
+    let tile_json = match tile_json {
+        Some(tile_json) => tile_json,
+        None => return (min_lod, max_lod),
+    };
+
+    let zoom = |v3: &str, v2: &str| {
+        tile_json
+            .get(v3)
+            .or_else(|| tile_json.get(v2))
+            .and_then(|zoom| match zoom {
+                Value::String(zoom) => zoom.parse::<u8>().ok(),
+                zoom => zoom.as_u64().and_then(|zoom| u8::try_from(zoom).ok()),
+            })
+    };
+
+    match (zoom("minzoom", "min_zoom"), zoom("maxzoom", "max_zoom")) {
+        (Some(min), Some(max)) if min <= max => (min_lod.min(min), max_lod.max(max)),
+        _ => (min_lod, max_lod),
+    }
+}
+
+/// Updates the zoom range of an existing tile.json in `dir`, if there is one.
+pub fn update_zoom_range(dir: &Path, min_lod: u8, max_lod: u8) -> anyhow::Result<()> {
+    let path = dir.join("tile.json");
+    if !path.is_file() {
+        return Ok(());
+    }
+
+    let mut tile_json: Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    if tile_json.get("minzoom").is_some() {
+        tile_json["minzoom"] = min_lod.into();
+        tile_json["maxzoom"] = max_lod.into();
+    } else {
+        tile_json["min_zoom"] = min_lod.into();
+        tile_json["max_zoom"] = max_lod.into();
+    }
+
+    fs::write(path, to_string_pretty(&tile_json)?)?;
+
+    Ok(())
+}
+
+/// Appends a sentence to the description of the tile.json in `dir`.
+pub fn append_description(dir: &Path, text: &str) -> anyhow::Result<()> {
+    let path = dir.join("tile.json");
+
+    let mut tile_json: Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    let description = tile_json["description"].as_str().unwrap_or_default();
+    tile_json["description"] = format!("{}. {}", description, text).into();
+
+    fs::write(path, to_string_pretty(&tile_json)?)?;
+
+    Ok(())
+}
+
+/// Rewrites the tile.json in `dir` as TileJSON 3.0.0. `tiles` are the URL
+/// templates of the tiles relative to `dir`.
+pub fn upgrade_to_v3(dir: &Path, tiles: Vec<String>) -> anyhow::Result<()> {
+    let path = dir.join("tile.json");
+
+    let tile_json: Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    fs::write(path, to_string_pretty(&to_v3(tile_json, tiles))?)?;
+
+    Ok(())
+}
+
+fn to_v3(mut tile_json: Value, tiles: Vec<String>) -> Value {
+    let fields = match tile_json.as_object_mut() {
+        Some(fields) => fields,
+        None => return tile_json,
+    };
+
+    fields.remove("tile_json");
+    fields.insert(String::from("tilejson"), Value::from("3.0.0"));
+    fields.insert(String::from("tiles"), Value::from(tiles));
+
+    // zoom levels have to be integers, older viewers sometimes wrote strings
+    for (old, new) in [("min_zoom", "minzoom"), ("max_zoom", "maxzoom")] {
+        let zoom = fields.remove(old).and_then(|zoom| match zoom {
+            Value::String(zoom) => zoom.parse::<u8>().ok(),
+            zoom => zoom.as_u64().map(|zoom| zoom as u8),
+        });
+        if let Some(zoom) = zoom {
+            fields.insert(String::from(new), Value::from(zoom));
+        }
+    }
+
+    // vector_layers is only allowed (and then required) for vector tiles
+    let vector_layers = fields
+        .remove("snake_case")
+        .filter(|layers| layers.as_array().is_some_and(|layers| !layers.is_empty()));
+    if let Some(vector_layers) = vector_layers {
+        fields.insert(String::from("vector_layers"), vector_layers);
+    }
+
+    tile_json
+}
+
 fn layer_fields(layer_name: &String) -> HashMap<String, String> {
     if layer_name == "house" {
         return [
@@ -140,5 +250,71 @@ fn layer_fields(layer_name: &String) -> HashMap<String, String> {
         .collect();
     }
 
-    return HashMap::new();
+    HashMap::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_zoom_range, to_v3};
+    use serde_json::json;
+    use tempdir::TempDir;
+
+    #[test]
+    fn converts_to_v3() {
+        let v2 = json!({
+            "tile_json": "2.2.0",
+            "name": "Altis Satellite Tiles",
+            "min_zoom": 0,
+            "max_zoom": "5",
+            "snake_case": [],
+            "crs": { "type": "arma" }
+        });
+
+        let v3 = to_v3(v2, vec![String::from("{z}/{x}/{y}.png")]);
+
+        assert_eq!(
+            json!({
+                "tilejson": "3.0.0",
+                "tiles": ["{z}/{x}/{y}.png"],
+                "name": "Altis Satellite Tiles",
+                "minzoom": 0,
+                "maxzoom": 5,
+                "crs": { "type": "arma" }
+            }),
+            v3
+        );
+    }
+
+    #[test]
+    fn keeps_vector_layers() {
+        let v2 = json!({ "snake_case": [{ "id": "house", "fields": {} }] });
+
+        let v3 = to_v3(v2, Vec::new());
+
+        assert_eq!(
+            json!([{ "id": "house", "fields": {} }]),
+            v3["vector_layers"]
+        );
+    }
+
+    #[test]
+    fn merges_zoom_range_with_existing_tile_json() {
+        let dir = TempDir::new("meh-utils-rust-tilejson").unwrap();
+        assert_eq!((2, 3), merge_zoom_range(dir.path(), 2, 3));
+
+        std::fs::write(
+            dir.path().join("tile.json"),
+            r#"{"min_zoom": 0, "max_zoom": 5}"#,
+        )
+        .unwrap();
+        assert_eq!((0, 5), merge_zoom_range(dir.path(), 2, 3));
+        assert_eq!((0, 7), merge_zoom_range(dir.path(), 6, 7));
+
+        std::fs::write(
+            dir.path().join("tile.json"),
+            r#"{"minzoom": 1, "maxzoom": 4}"#,
+        )
+        .unwrap();
+        assert_eq!((0, 4), merge_zoom_range(dir.path(), 0, 2));
+    }
 }
@@ -0,0 +1,57 @@
+use std::{cell::RefCell, fs::File, path::Path};
+
+use anyhow::Context;
+
+use tracing::span::EnteredSpan;
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::{filter::LevelFilter, prelude::*};
+
+/*
+    Spans recorded with --trace:
+
+    command     the whole run of a command (INFO)
+    stage       from one step!() to its done!() (INFO)
+    lod         building the tiles of one LOD (DEBUG)
+    tile        resizing/rendering and writing a single tile (TRACE)
+    encode      encoding a tile in one format (TRACE)
+*/
+
+thread_local! {
+    static STAGE: RefCell<Option<EnteredSpan>> = const { RefCell::new(None) };
+}
+
+/// Records spans into a Chrome trace file (for chrome://tracing or
+/// ui.perfetto.dev) until the returned guard is dropped. Spans of single
+/// tiles are only recorded with `tiles`, as they blow up the file on big maps.
+pub fn init(path: &Path, tiles: bool) -> anyhow::Result<FlushGuard> {
+    let file = File::create(path)
+        .with_context(|| format!("Couldn't create trace file {}", path.display()))?;
+    let (layer, guard) = ChromeLayerBuilder::new()
+        .writer(file)
+        .include_args(true)
+        .build();
+    let level = if tiles {
+        LevelFilter::TRACE
+    } else {
+        LevelFilter::DEBUG
+    };
+
+    tracing_subscriber::registry()
+        .with(layer.with_filter(level))
+        .init();
+
+    Ok(guard)
+}
+
+/// Ends the current stage of this thread (if any) and starts a new one.
+pub fn start_stage(name: &str) {
+    STAGE.with(|stage| {
+        let mut stage = stage.borrow_mut();
+        stage.take();
+        *stage = Some(tracing::info_span!("stage", name).entered());
+    });
+}
+
+pub fn end_stage() {
+    STAGE.with(|stage| stage.borrow_mut().take());
+}
@@ -0,0 +1,59 @@
+use std::ops::RangeInclusive;
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+/// Calls `build` for every LOD of `lods`, up to `parallel` LODs at the same
+/// time. The tiles of low LODs are resized from huge parts of the source
+/// image, so they barely keep more than one thread busy on their own.
+/// Limiting the number of concurrent LODs bounds the memory used for their
+/// tiles in flight (and the resampled sources of web mercator zoom levels).
+pub fn build_lods(
+    lods: RangeInclusive<u8>,
+    parallel: usize,
+    build: impl Fn(u8) -> anyhow::Result<()> + Sync,
+) -> anyhow::Result<()> {
+    let lods: Vec<u8> = lods.collect();
+
+    for batch in lods.chunks(parallel.max(1)) {
+        batch.par_iter().try_for_each(|lod| build(*lod))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_lods;
+    use std::sync::Mutex;
+
+    #[test]
+    fn builds_every_lod_once() {
+        let built = Mutex::new(Vec::new());
+
+        build_lods(2..=6, 2, |lod| {
+            built.lock().unwrap().push(lod);
+            Ok(())
+        })
+        .unwrap();
+
+        let mut built = built.into_inner().unwrap();
+        built.sort_unstable();
+        assert_eq!(vec![2, 3, 4, 5, 6], built);
+    }
+
+    #[test]
+    fn stops_after_failed_batch() {
+        let built = Mutex::new(Vec::new());
+
+        let result = build_lods(0..=3, 2, |lod| {
+            built.lock().unwrap().push(lod);
+            match lod {
+                1 => anyhow::bail!("LOD 1 failed"),
+                _ => Ok(()),
+            }
+        });
+
+        assert!(result.is_err());
+        assert!(built.into_inner().unwrap().iter().all(|lod| *lod < 2));
+    }
+}
@@ -1,22 +1,13 @@
-use std::{fs::create_dir_all, panic, path::Path};
+use std::panic;
 
 use image::{imageops, DynamicImage, GenericImageView, Rgba};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-use super::{encode_png, TileError, TILE_SIZE_IN_PX};
+use super::{write_on_encoder_threads, TileError, TileWriter, TILE_SIZE_IN_PX};
 
-pub fn build_tile_set(set_base_path: &Path, img: &DynamicImage, lod: u8) -> anyhow::Result<()> {
+pub fn build_tile_set(writer: &TileWriter, img: &DynamicImage, lod: u8) -> anyhow::Result<()> {
     let tiles_per_row_col = 2u32.pow(lod as u32);
 
-    // generate all column directories
-    (0..tiles_per_row_col)
-        .into_par_iter()
-        .panic_fuse()
-        .for_each(|col| {
-            let file_path = set_base_path.join(lod.to_string()).join(col.to_string());
-            create_dir_all(file_path).unwrap();
-        });
-
     let (width, height) = img.dimensions();
 
     let tile_width = width / tiles_per_row_col;
@@ -25,35 +16,57 @@ pub fn build_tile_set(set_base_path: &Path, img: &DynamicImage, lod: u8) -> anyh
     let width_remainder = width % tiles_per_row_col;
     let height_remainder = height % tiles_per_row_col;
 
-    let result = panic::catch_unwind(|| {
+    let lod_span = tracing::debug_span!("lod", lod);
+    let _lod_span = lod_span.enter();
+
+    let tile = |index: u32| -> (u32, u32, DynamicImage) {
+        let col = index / tiles_per_row_col;
+        let row = index % tiles_per_row_col;
+        let x = tile_width * col;
+        let y = tile_height * row;
+        let mut w = tile_width;
+        let mut h = tile_height;
+
+        // distribute remaining pixels over the first X rows / cols
+        if width_remainder > col + 1 {
+            w += 1;
+        }
+        if height_remainder > row + 1 {
+            h += 1;
+        }
+
+        (col, row, resize(&*img.view(x, y, w, h)))
+    };
+
+    let indices = || {
         (0..tiles_per_row_col * tiles_per_row_col)
             .into_par_iter()
-            .panic_fuse()
-            .for_each(|index| {
-                let col = index / tiles_per_row_col;
-                let row = index % tiles_per_row_col;
-                let x = tile_width * col;
-                let y = tile_height * row;
-                let mut w = tile_width;
-                let mut h = tile_height;
-
-                // distribute remaining pixels over the first X rows / cols
-                if width_remainder > col + 1 {
-                    w = w + 1;
-                }
-                if height_remainder > row + 1 {
-                    h = h + 1;
-                }
-
-                let sub = img.view(x, y, w, h);
-                let resized = resize(&sub);
-
-                match write_tile(set_base_path, &resized, col, row, lod) {
-                    Ok(_) => {}
-                    Err(e) => panic::panic_any(TileError::new(col, row, e)),
-                };
+            .filter(|index| {
+                writer.includes_tile(index / tiles_per_row_col, index % tiles_per_row_col, lod)
+            })
+    };
+
+    if let Some(threads) = writer.encoder_threads() {
+        return write_on_encoder_threads(writer, threads, lod, |queue| {
+            let _ = indices().try_for_each(|index| {
+                let _span = tracing::trace_span!(parent: &lod_span, "tile", index).entered();
+                let (col, row, resized) = tile(index);
+                queue(resized, col, row).then_some(()).ok_or(())
             });
-    });
+        });
+    }
+
+    // the span is only read, so it's fine to use it across the unwind boundary
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        indices().panic_fuse().for_each(|index| {
+            let _span = tracing::trace_span!(parent: &lod_span, "tile", index).entered();
+            let (col, row, resized) = tile(index);
+
+            if let Err(e) = writer.write_tile(&resized, col, row, lod) {
+                panic::panic_any(e);
+            }
+        });
+    }));
 
     result.map_err::<anyhow::Error, _>(|e| {
         let tile_error = e.downcast_ref::<TileError>().unwrap();
@@ -71,17 +84,3 @@ fn resize<I: GenericImageView<Pixel = Rgba<u8>>>(image: &I) -> DynamicImage {
 
     DynamicImage::ImageRgba8(buffer)
 }
-
-fn write_tile(
-    set_base_path: &Path,
-    img: &DynamicImage,
-    x: u32,
-    y: u32,
-    z: u8,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let file_path = set_base_path
-        .join(z.to_string())
-        .join(x.to_string())
-        .join(format!("{}.png", y.to_string()));
-    encode_png(&file_path, img)
-}
@@ -0,0 +1,109 @@
+use image::{DynamicImage, RgbaImage};
+use rayon::{iter::ParallelIterator, slice::ParallelSliceMut};
+
+/// Color adjustments applied to every pixel. Alpha stays untouched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorCorrection {
+    /// added to every channel (-1 to 1)
+    pub brightness: f32,
+    /// factor for the distance of every channel to 0.5
+    pub contrast: f32,
+    /// factor for the distance of every channel to the luminance of the pixel
+    pub saturation: f32,
+    /// values greater than 1 brighten dark areas
+    pub gamma: f32,
+}
+
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        ColorCorrection {
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl ColorCorrection {
+    pub fn is_identity(&self) -> bool {
+        *self == ColorCorrection::default()
+    }
+
+    /// Applies brightness, contrast, saturation and gamma (in that order).
+    pub fn apply(&self, img: DynamicImage) -> DynamicImage {
+        if self.is_identity() {
+            return img;
+        }
+
+        let mut buffer: RgbaImage = img.into_rgba8();
+
+        buffer.par_chunks_mut(4).for_each(|pixel| {
+            let mut rgb = [pixel[0], pixel[1], pixel[2]].map(|c| c as f32 / 255.0);
+
+            for c in rgb.iter_mut() {
+                *c = (*c + self.brightness - 0.5) * self.contrast + 0.5;
+            }
+
+            let luminance = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+            for c in rgb.iter_mut() {
+                *c = luminance + (*c - luminance) * self.saturation;
+                *c = c.clamp(0.0, 1.0).powf(1.0 / self.gamma);
+            }
+
+            for (channel, c) in pixel.iter_mut().zip(rgb) {
+                *channel = (c * 255.0).round() as u8;
+            }
+        });
+
+        DynamicImage::ImageRgba8(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ColorCorrection;
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    fn pixel(color: [u8; 4], correction: ColorCorrection) -> [u8; 4] {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba(color)));
+
+        correction.apply(img).into_rgba8().get_pixel(0, 0).0
+    }
+
+    #[test]
+    fn default_keeps_pixels() {
+        assert_eq!(
+            [12, 34, 56, 78],
+            pixel([12, 34, 56, 78], ColorCorrection::default())
+        );
+    }
+
+    #[test]
+    fn adjusts_channels_but_not_alpha() {
+        let brighter = ColorCorrection {
+            brightness: 0.2,
+            ..Default::default()
+        };
+        assert_eq!([51, 51, 51, 10], pixel([0, 0, 0, 10], brighter));
+
+        let more_contrast = ColorCorrection {
+            contrast: 2.0,
+            ..Default::default()
+        };
+        assert_eq!([0, 255, 0, 255], pixel([32, 224, 0, 255], more_contrast));
+
+        let greyscale = ColorCorrection {
+            saturation: 0.0,
+            ..Default::default()
+        };
+        let [r, g, b, _] = pixel([255, 0, 0, 255], greyscale);
+        assert!(r == g && g == b);
+
+        let gamma = ColorCorrection {
+            gamma: 2.0,
+            ..Default::default()
+        };
+        assert_eq!([128, 0, 255, 255], pixel([64, 0, 255, 255], gamma));
+    }
+}
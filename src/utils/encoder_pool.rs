@@ -0,0 +1,95 @@
+use anyhow::anyhow;
+use image::DynamicImage;
+use std::{
+    sync::{mpsc::sync_channel, Arc, Mutex},
+    thread,
+};
+
+use super::{TileError, TileWriter};
+
+/// Queues a tile for encoding. Returns `false` once writing a tile failed,
+/// after which producing more tiles is pointless.
+pub type QueueTile<'a> = &'a (dyn Fn(DynamicImage, u32, u32) -> bool + Sync);
+
+/// Encodes and writes the tiles of `lod` on `threads` dedicated threads, so
+/// encoding doesn't have to wait for resizing on the rayon pool (and vice versa).
+/// `produce` is called with a function to queue tiles with. The queue is
+/// bounded, so producing stalls when encoding can't keep up instead of piling
+/// up images in memory.
+pub fn write_on_encoder_threads(
+    writer: &TileWriter,
+    threads: usize,
+    lod: u8,
+    produce: impl FnOnce(QueueTile),
+) -> anyhow::Result<()> {
+    let threads = threads.max(1);
+    let (sender, receiver) = sync_channel::<(DynamicImage, u32, u32)>(threads * 2);
+    let receiver = Arc::new(Mutex::new(receiver));
+    let error: Mutex<Option<TileError>> = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let receiver = Arc::clone(&receiver);
+            let error = &error;
+
+            scope.spawn(move || loop {
+                let next = receiver.lock().unwrap().recv();
+                let (img, col, row) = match next {
+                    Ok(tile) => tile,
+                    Err(_) => break,
+                };
+
+                if error.lock().unwrap().is_some() {
+                    break;
+                }
+
+                if let Err(e) = writer.write_tile(&img, col, row, lod) {
+                    error.lock().unwrap().get_or_insert(e);
+                    break;
+                }
+            });
+        }
+        // the receiver is gone as soon as all encoder threads stopped
+        drop(receiver);
+
+        produce(&|img, col, row| sender.send((img, col, row)).is_ok());
+        drop(sender);
+    });
+
+    match error.into_inner().unwrap() {
+        Some(tile_error) => Err(anyhow!("{}", tile_error)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_on_encoder_threads;
+    use crate::utils::{parse_formats, TilePathTemplate, TileWriter};
+    use image::{DynamicImage, RgbaImage};
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+    use tempdir::TempDir;
+
+    #[test]
+    fn writes_all_queued_tiles() {
+        let dir = TempDir::new("meh-utils-rust-encoder").unwrap();
+        let writer = TileWriter::new(
+            dir.path(),
+            TilePathTemplate::default(),
+            parse_formats("png").unwrap(),
+        )
+        .unwrap();
+
+        write_on_encoder_threads(&writer, 2, 1, |queue| {
+            (0..4u32).into_par_iter().for_each(|index| {
+                let img = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+                queue(img, index / 2, index % 2);
+            });
+        })
+        .unwrap();
+
+        for (col, row) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            assert!(dir.path().join(format!("1/{}/{}.png", col, row)).is_file());
+        }
+    }
+}
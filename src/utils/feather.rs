@@ -0,0 +1,124 @@
+use image::{imageops::crop_imm, RgbaImage};
+
+/// Softens hard seams between images which were placed next to each other.
+/// Pixels within `band` pixels of a seam are blended with their mirror image
+/// on the other side of the seam. The blend fades from 50/50 at the seam to
+/// nothing at the edge of the band.
+///
+/// `seams_x` are the columns where an image starts right of another one,
+/// `seams_y` the rows where an image starts below another one.
+pub fn feather_seams(img: &mut RgbaImage, seams_x: &[u32], seams_y: &[u32], band: u32) {
+    if band == 0 {
+        return;
+    }
+
+    let (width, height) = img.dimensions();
+
+    for &seam in seams_x.iter().filter(|seam| **seam > 0 && **seam < width) {
+        let band = band.min(seam).min(width - seam);
+        // only the pixels within the band on both sides of the seam are read
+        let strip = crop_imm(img, seam - band, 0, 2 * band, height).to_image();
+        let origin = (seam - band, 0);
+
+        for offset in 0..band {
+            let weight = 0.5 * (1.0 - offset as f32 / band as f32);
+            let left = band - 1 - offset;
+            let right = band + offset;
+
+            for y in 0..height {
+                blend(img, &strip, origin, (left, y), (right, y), weight);
+                blend(img, &strip, origin, (right, y), (left, y), weight);
+            }
+        }
+    }
+
+    for &seam in seams_y.iter().filter(|seam| **seam > 0 && **seam < height) {
+        let band = band.min(seam).min(height - seam);
+        let strip = crop_imm(img, 0, seam - band, width, 2 * band).to_image();
+        let origin = (0, seam - band);
+
+        for offset in 0..band {
+            let weight = 0.5 * (1.0 - offset as f32 / band as f32);
+            let top = band - 1 - offset;
+            let bottom = band + offset;
+
+            for x in 0..width {
+                blend(img, &strip, origin, (x, top), (x, bottom), weight);
+                blend(img, &strip, origin, (x, bottom), (x, top), weight);
+            }
+        }
+    }
+}
+
+/// Blends the pixel at `at` with the one at `other`, both given within
+/// `strip`, which was copied from `img` at `origin`.
+fn blend(
+    img: &mut RgbaImage,
+    strip: &RgbaImage,
+    origin: (u32, u32),
+    at: (u32, u32),
+    other: (u32, u32),
+    weight: f32,
+) {
+    let own = strip.get_pixel(at.0, at.1);
+    let mirrored = strip.get_pixel(other.0, other.1);
+
+    // don't pull in transparent pixels from outside of the image
+    if mirrored[3] == 0 {
+        return;
+    }
+
+    let pixel = img.get_pixel_mut(origin.0 + at.0, origin.1 + at.1);
+    for channel in 0..4 {
+        let value = own[channel] as f32 * (1.0 - weight) + mirrored[channel] as f32 * weight;
+        pixel[channel] = value.round() as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::feather_seams;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn blends_across_vertical_seam() {
+        let mut img = RgbaImage::from_fn(8, 1, |x, _| {
+            if x < 4 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([200, 200, 200, 255])
+            }
+        });
+
+        feather_seams(&mut img, &[4], &[], 2);
+
+        let reds: Vec<_> = img.pixels().map(|pixel| pixel[0]).collect();
+        assert_eq!(vec![0, 0, 50, 100, 100, 150, 200, 200], reds);
+    }
+
+    #[test]
+    fn blends_across_horizontal_seam() {
+        let mut img = RgbaImage::from_fn(2, 6, |_, y| {
+            if y < 3 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([200, 200, 200, 255])
+            }
+        });
+
+        feather_seams(&mut img, &[], &[3], 4);
+
+        let reds: Vec<_> = (0..6).map(|y| img.get_pixel(1, y)[0]).collect();
+        assert_eq!(vec![33, 67, 100, 100, 133, 167], reds);
+    }
+
+    #[test]
+    fn zero_band_keeps_image() {
+        let mut img = RgbaImage::from_fn(4, 4, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let original = img.clone();
+
+        feather_seams(&mut img, &[2], &[2], 0);
+
+        assert_eq!(original, img);
+    }
+}
@@ -0,0 +1,133 @@
+use image::DynamicImage;
+use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+
+type Histogram = [[u64; 256]; 3];
+
+/// Matches the color histograms of all images to their combined histogram,
+/// which evens out exposure differences between images of a mosaic.
+/// Transparent pixels are ignored and alpha stays untouched.
+pub fn match_histograms(images: &mut [DynamicImage]) {
+    for img in images.iter_mut() {
+        if !matches!(img, DynamicImage::ImageRgba8(_)) {
+            *img = DynamicImage::ImageRgba8(img.to_rgba8());
+        }
+    }
+
+    let histograms: Vec<Histogram> = images.iter().map(histogram).collect();
+
+    let mut reference = [[0u64; 256]; 3];
+    for hist in &histograms {
+        for (channel, values) in hist.iter().enumerate() {
+            for (value, count) in values.iter().enumerate() {
+                reference[channel][value] += count;
+            }
+        }
+    }
+    let reference_cdf = reference.map(|values| cdf(&values));
+
+    let mut work: Vec<_> = images.iter_mut().zip(histograms).collect();
+    work.par_iter_mut().for_each(|(img, hist)| {
+        let luts: Vec<[u8; 256]> = (0..3)
+            .map(|channel| lookup_table(&cdf(&hist[channel]), &reference_cdf[channel]))
+            .collect();
+
+        if let DynamicImage::ImageRgba8(buffer) = img {
+            for pixel in buffer.pixels_mut() {
+                if pixel[3] == 0 {
+                    continue;
+                }
+                for channel in 0..3 {
+                    pixel[channel] = luts[channel][pixel[channel] as usize];
+                }
+            }
+        }
+    });
+}
+
+fn histogram(img: &DynamicImage) -> Histogram {
+    let mut hist = [[0u64; 256]; 3];
+
+    if let DynamicImage::ImageRgba8(buffer) = img {
+        for pixel in buffer.pixels().filter(|pixel| pixel[3] != 0) {
+            for (channel, values) in hist.iter_mut().enumerate() {
+                values[pixel[channel] as usize] += 1;
+            }
+        }
+    }
+
+    hist
+}
+
+/// Normalized cumulative distribution of a histogram.
+fn cdf(values: &[u64; 256]) -> [f64; 256] {
+    let total: u64 = values.iter().sum();
+    let mut cdf = [0.0; 256];
+    let mut sum = 0;
+
+    for (value, count) in values.iter().enumerate() {
+        sum += count;
+        cdf[value] = if total == 0 {
+            1.0
+        } else {
+            sum as f64 / total as f64
+        };
+    }
+
+    cdf
+}
+
+/// Maps every value to the smallest reference value with at least the same cumulative share.
+fn lookup_table(source: &[f64; 256], reference: &[f64; 256]) -> [u8; 256] {
+    let mut lut = [0u8; 256];
+    let mut target = 0;
+
+    for (value, share) in source.iter().enumerate() {
+        while target < 255 && reference[target] < *share - f64::EPSILON {
+            target += 1;
+        }
+        lut[value] = target as u8;
+    }
+
+    lut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::match_histograms;
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    fn image(pixels: &[[u8; 4]]) -> DynamicImage {
+        let mut buffer = RgbaImage::new(pixels.len() as u32, 1);
+        for (x, pixel) in pixels.iter().enumerate() {
+            buffer.put_pixel(x as u32, 0, Rgba(*pixel));
+        }
+        DynamicImage::ImageRgba8(buffer)
+    }
+
+    fn red_values(img: &DynamicImage) -> Vec<u8> {
+        img.to_rgba8().pixels().map(|pixel| pixel[0]).collect()
+    }
+
+    #[test]
+    fn evens_out_exposure() {
+        let mut images = vec![
+            image(&[[10, 0, 0, 255], [20, 0, 0, 255]]),
+            image(&[[110, 0, 0, 255], [120, 0, 0, 255]]),
+        ];
+
+        match_histograms(&mut images);
+
+        // both images now use the combined range of values in the same way
+        assert_eq!(red_values(&images[0]), red_values(&images[1]));
+    }
+
+    #[test]
+    fn ignores_transparent_pixels() {
+        let mut images = vec![image(&[[10, 20, 30, 0], [40, 50, 60, 255]])];
+
+        match_histograms(&mut images);
+
+        assert_eq!([10, 20, 30, 0], images[0].to_rgba8().get_pixel(0, 0).0);
+        assert_eq!([40, 50, 60, 255], images[0].to_rgba8().get_pixel(1, 0).0);
+    }
+}
@@ -1,13 +1,42 @@
+mod build_lods;
 mod build_tile_set;
+mod color_correction;
+mod encoder_pool;
+mod feather;
+mod histogram_matching;
+mod pad;
+mod pmtiles;
+mod tile_archive;
 mod tile_error;
+mod tile_format;
+mod tile_path;
+mod tile_stats;
+mod tile_writer;
+mod watermark;
+mod web_mercator;
+mod write_limiter;
 
-use image::{codecs::png::PngEncoder, DynamicImage, GenericImageView};
+use image::{codecs::png::PngEncoder, DynamicImage, GenericImageView, ImageEncoder};
 use std::fs::File;
-use std::io::{BufWriter, Error, ErrorKind};
+use std::io::{BufWriter, Error};
 use std::path::Path;
 
+pub use build_lods::build_lods;
 pub use build_tile_set::build_tile_set;
+pub use color_correction::ColorCorrection;
+pub use encoder_pool::write_on_encoder_threads;
+pub use feather::feather_seams;
+pub use histogram_matching::match_histograms;
+pub use pad::pad_to_tile_grid;
+pub use tile_archive::TileArchive;
 pub use tile_error::TileError;
+pub use tile_format::{parse_formats, TileFormats};
+pub use tile_path::{TilePathTemplate, DEFAULT_TILE_PATH};
+pub use tile_stats::TileStats;
+pub use tile_writer::TileWriter;
+pub use watermark::Watermark;
+pub use web_mercator::{build_mercator_tile_set, Georeference};
+pub use write_limiter::WriteLimiter;
 
 pub const TILE_SIZE_IN_PX: u32 = 256;
 
@@ -16,7 +45,27 @@ pub fn calc_max_lod(image: &DynamicImage) -> u8 {
 
     let tiles_per_row = (width / TILE_SIZE_IN_PX as f32).ceil();
 
-    return tiles_per_row.log2().ceil() as u8;
+    tiles_per_row.log2().ceil() as u8
+}
+
+/// Narrows the calculated LOD range down to the requested one.
+pub fn limit_lod_range(
+    (min_lod, max_lod): (u8, u8),
+    requested_min: Option<u8>,
+    requested_max: Option<u8>,
+) -> anyhow::Result<(u8, u8)> {
+    let min = requested_min.map_or(min_lod, |lod| lod.max(min_lod));
+    let max = requested_max.map_or(max_lod, |lod| lod.min(max_lod));
+
+    if min > max {
+        anyhow::bail!(
+            "Requested LOD range doesn't overlap with the available LODs {}-{}",
+            min_lod,
+            max_lod
+        );
+    }
+
+    Ok((min, max))
 }
 
 pub fn encode_png(
@@ -24,12 +73,12 @@ pub fn encode_png(
     img: &DynamicImage,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let file = File::create(file_path)?;
-    let ref mut buf = BufWriter::new(file);
+    let buf = &mut BufWriter::new(file);
     let encoder = PngEncoder::new(buf);
 
     let dim = img.dimensions();
-    match encoder.encode(&img.to_bytes(), dim.0, dim.1, img.color()) {
+    match encoder.write_image(img.as_bytes(), dim.0, dim.1, img.color()) {
         Ok(_) => Ok(()),
-        Err(err) => Err(Box::new(Error::new(ErrorKind::Other, err.to_string()))),
+        Err(err) => Err(Box::new(Error::other(err.to_string()))),
     }
 }
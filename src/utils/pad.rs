@@ -0,0 +1,44 @@
+use image::{imageops::replace, DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+/// Pads `img` at the right and bottom to a square with a power of two side
+/// length, so every tile of every LOD covers the same number of source pixels
+/// and non-square images aren't stretched. Images which already are such a
+/// square are returned as they are.
+pub fn pad_to_tile_grid(img: DynamicImage, fill: Rgba<u8>) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    let side = width.max(height).next_power_of_two();
+
+    if width == side && height == side {
+        return img;
+    }
+
+    let mut padded = RgbaImage::from_pixel(side, side, fill);
+    replace(&mut padded, &img.to_rgba8(), 0, 0);
+
+    DynamicImage::ImageRgba8(padded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pad_to_tile_grid;
+    use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+    #[test]
+    fn pads_to_power_of_two_square() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(300, 200, Rgba([255; 4])));
+
+        let padded = pad_to_tile_grid(img, Rgba([0; 4]));
+
+        assert_eq!((512, 512), padded.dimensions());
+        assert_eq!(Rgba([255; 4]), padded.get_pixel(299, 199));
+        assert_eq!(Rgba([0; 4]), padded.get_pixel(300, 0));
+        assert_eq!(Rgba([0; 4]), padded.get_pixel(0, 200));
+    }
+
+    #[test]
+    fn keeps_aligned_images() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(256, 256, Rgba([255; 4])));
+
+        assert_eq!((256, 256), pad_to_tile_grid(img, Rgba([0; 4])).dimensions());
+    }
+}
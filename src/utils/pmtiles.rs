@@ -0,0 +1,384 @@
+use anyhow::{bail, Context};
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+const HEADER_LEN: usize = 127;
+/// The header and root directory have to fit into the first 16 KiB, so
+/// clients can fetch both with a single request.
+const ROOT_LEN: usize = 16384 - HEADER_LEN;
+
+const COMPRESSION_NONE: u8 = 1;
+const COMPRESSION_GZIP: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+    tile_id: u64,
+    offset: u64,
+    length: u32,
+    run_length: u32,
+}
+
+/// Writer of a PMTiles v3 archive (https://github.com/protomaps/PMTiles).
+///
+/// Tiles are appended to a temporary file next to the archive in the order
+/// they are written, the directories are built once all tiles are known.
+pub struct PMTilesWriter {
+    path: PathBuf,
+    data_path: PathBuf,
+    data: BufWriter<File>,
+    data_len: u64,
+    entries: Vec<Entry>,
+    tile_type: Option<u8>,
+    min_zoom: u8,
+    max_zoom: u8,
+}
+
+impl PMTilesWriter {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let data_path = path.with_extension("pmtiles.tmp");
+        let data = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&data_path)
+            .with_context(|| format!("Couldn't create {}", data_path.display()))?;
+
+        Ok(PMTilesWriter {
+            path: path.to_path_buf(),
+            data_path,
+            data: BufWriter::new(data),
+            data_len: 0,
+            entries: Vec::new(),
+            tile_type: None,
+            min_zoom: u8::MAX,
+            max_zoom: 0,
+        })
+    }
+
+    /// Adds the tile `z`/`x`/`y`. PMTiles archives hold a single tile type,
+    /// which is taken from the `extension` of the first tile.
+    pub fn append(
+        &mut self,
+        z: u8,
+        x: u32,
+        y: u32,
+        extension: &str,
+        data: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let tile_type = match extension {
+            "pbf" | "mvt" => 1,
+            "png" => 2,
+            "jpg" | "jpeg" => 3,
+            "webp" => 4,
+            _ => 0,
+        };
+        match self.tile_type {
+            Some(existing) if existing != tile_type => {
+                return Err("PMTiles archives can only hold a single tile format".into())
+            }
+            _ => self.tile_type = Some(tile_type),
+        }
+
+        self.data.write_all(data)?;
+        self.entries.push(Entry {
+            tile_id: tile_id(z, x, y),
+            offset: self.data_len,
+            length: data.len() as u32,
+            run_length: 1,
+        });
+        self.data_len += data.len() as u64;
+        self.min_zoom = self.min_zoom.min(z);
+        self.max_zoom = self.max_zoom.max(z);
+
+        Ok(())
+    }
+
+    /// Writes header, directories and tile data to the archive and removes
+    /// the temporary file. If that fails, the incomplete archive is removed.
+    pub fn finish(self) -> anyhow::Result<()> {
+        let path = self.path.clone();
+        let data_path = self.data_path.clone();
+
+        let result = self.write_archive();
+        if result.is_err() {
+            let _ = fs::remove_file(&path);
+        }
+        let _ = fs::remove_file(&data_path);
+
+        result
+    }
+
+    /// Removes the temporary file without writing the archive.
+    pub fn discard(self) {
+        let data_path = self.data_path.clone();
+        drop(self);
+        let _ = fs::remove_file(&data_path);
+    }
+
+    fn write_archive(mut self) -> anyhow::Result<()> {
+        self.data.flush()?;
+        self.entries.sort_by_key(|entry| entry.tile_id);
+        if let Some(pair) = self
+            .entries
+            .windows(2)
+            .find(|pair| pair[0].tile_id == pair[1].tile_id)
+        {
+            bail!("Tile {} was written twice", pair[0].tile_id);
+        }
+
+        let (root, leaves) = build_directories(&self.entries)?;
+        let metadata = gzip(b"{}")?;
+
+        let root_offset = HEADER_LEN as u64;
+        let metadata_offset = root_offset + root.len() as u64;
+        let leaves_offset = metadata_offset + metadata.len() as u64;
+        let data_offset = leaves_offset + leaves.len() as u64;
+        let tiles = self.entries.len() as u64;
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(b"PMTiles");
+        header.push(3);
+        for value in [
+            root_offset,
+            root.len() as u64,
+            metadata_offset,
+            metadata.len() as u64,
+            leaves_offset,
+            leaves.len() as u64,
+            data_offset,
+            self.data_len,
+            tiles,
+            tiles,
+            tiles,
+        ] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        // tile data is in the order the tiles were written, not by tile id
+        header.push(0);
+        header.push(COMPRESSION_GZIP);
+        header.push(COMPRESSION_NONE);
+        header.push(self.tile_type.unwrap_or(0));
+        header.push(if tiles == 0 { 0 } else { self.min_zoom });
+        header.push(self.max_zoom);
+        // the tiles aren't georeferenced, so the bounds span the whole world
+        for degrees in [-180.0, -85.0, 180.0, 85.0] {
+            header.extend_from_slice(&e7(degrees).to_le_bytes());
+        }
+        header.push(if tiles == 0 { 0 } else { self.min_zoom });
+        header.extend_from_slice(&e7(0.0).to_le_bytes());
+        header.extend_from_slice(&e7(0.0).to_le_bytes());
+
+        let file = File::create(&self.path)
+            .with_context(|| format!("Couldn't create archive {}", self.path.display()))?;
+        let mut out = BufWriter::new(file);
+        out.write_all(&header)?;
+        out.write_all(&root)?;
+        out.write_all(&metadata)?;
+        out.write_all(&leaves)?;
+
+        let mut data = self.data.into_inner().map_err(|err| err.into_error())?;
+        data.rewind()?;
+        io::copy(&mut data, &mut out)?;
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+fn e7(degrees: f64) -> i32 {
+    (degrees * 10_000_000.0) as i32
+}
+
+/// Position of the tile on the Hilbert curve of its zoom level, after all
+/// tiles of the lower zoom levels.
+fn tile_id(z: u8, x: u32, y: u32) -> u64 {
+    let lower_levels = ((1u64 << (2 * z as u64)) - 1) / 3;
+
+    let (mut x, mut y) = (x as u64, y as u64);
+    let mut d = 0;
+    let mut s = (1u64 << z) / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u64;
+        let ry = (y & s > 0) as u64;
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+
+    lower_levels + d
+}
+
+/// Gzipped root directory and leaf directories. If the entries don't fit
+/// into the root directory, they are split into leaves the root points to,
+/// which are made bigger until the root fits.
+fn build_directories(entries: &[Entry]) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let root = gzip(&serialize_directory(entries))?;
+    if root.len() <= ROOT_LEN {
+        return Ok((root, Vec::new()));
+    }
+
+    let mut leaf_size = 4096;
+    loop {
+        let mut leaves = Vec::new();
+        let mut root_entries = Vec::new();
+        for chunk in entries.chunks(leaf_size) {
+            let leaf = gzip(&serialize_directory(chunk))?;
+            root_entries.push(Entry {
+                tile_id: chunk[0].tile_id,
+                offset: leaves.len() as u64,
+                length: leaf.len() as u32,
+                // zero marks entries pointing to leaf directories
+                run_length: 0,
+            });
+            leaves.extend_from_slice(&leaf);
+        }
+
+        let root = gzip(&serialize_directory(&root_entries))?;
+        if root.len() <= ROOT_LEN {
+            return Ok((root, leaves));
+        }
+        leaf_size *= 2;
+    }
+}
+
+/// Directory as varints, column by column: tile ids as deltas, run lengths,
+/// lengths and offsets, which are zero if a tile directly follows the
+/// previous one and one more than the offset otherwise.
+fn serialize_directory(entries: &[Entry]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    write_varint(&mut out, entries.len() as u64);
+
+    let mut last_id = 0;
+    for entry in entries {
+        write_varint(&mut out, entry.tile_id - last_id);
+        last_id = entry.tile_id;
+    }
+    for entry in entries {
+        write_varint(&mut out, entry.run_length as u64);
+    }
+    for entry in entries {
+        write_varint(&mut out, entry.length as u64);
+    }
+    for (index, entry) in entries.iter().enumerate() {
+        let follows_previous = index > 0 && {
+            let previous = &entries[index - 1];
+            entry.offset == previous.offset + previous.length as u64
+        };
+        write_varint(
+            &mut out,
+            if follows_previous {
+                0
+            } else {
+                entry.offset + 1
+            },
+        );
+    }
+
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn gzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{serialize_directory, tile_id, Entry, PMTilesWriter};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use tempdir::TempDir;
+
+    #[test]
+    fn numbers_tiles_along_hilbert_curve() {
+        assert_eq!(0, tile_id(0, 0, 0));
+        assert_eq!(1, tile_id(1, 0, 0));
+        assert_eq!(2, tile_id(1, 0, 1));
+        assert_eq!(3, tile_id(1, 1, 1));
+        assert_eq!(4, tile_id(1, 1, 0));
+        assert_eq!(5, tile_id(2, 0, 0));
+        assert_eq!(20, tile_id(2, 3, 0));
+        assert_eq!(21, tile_id(3, 0, 0));
+    }
+
+    #[test]
+    fn serializes_directories() {
+        let entries = [
+            Entry {
+                tile_id: 1,
+                offset: 0,
+                length: 10,
+                run_length: 1,
+            },
+            Entry {
+                tile_id: 2,
+                offset: 10,
+                length: 200,
+                run_length: 1,
+            },
+            Entry {
+                tile_id: 4,
+                offset: 300,
+                length: 1,
+                run_length: 1,
+            },
+        ];
+
+        assert_eq!(
+            vec![3, 1, 1, 2, 1, 1, 1, 10, 200, 1, 1, 1, 0, 173, 2],
+            serialize_directory(&entries)
+        );
+    }
+
+    #[test]
+    fn writes_header_directory_and_tiles() {
+        let dir = TempDir::new("meh-utils-rust-pmtiles").unwrap();
+        let path = dir.path().join("tiles.pmtiles");
+
+        let mut writer = PMTilesWriter::create(&path).unwrap();
+        writer.append(1, 1, 0, "png", b"second").unwrap();
+        writer.append(0, 0, 0, "png", b"first").unwrap();
+        assert!(writer.append(1, 0, 0, "jpg", b"jpeg").is_err());
+        writer.finish().unwrap();
+
+        let file = std::fs::read(&path).unwrap();
+        let header_u64 = |offset: usize| {
+            u64::from_le_bytes(file[offset..offset + 8].try_into().unwrap()) as usize
+        };
+        assert_eq!(b"PMTiles\x03", &file[..8]);
+        assert_eq!(2, header_u64(72));
+        assert_eq!((2, 0, 1), (file[99], file[100], file[101]));
+
+        let (root_offset, root_len) = (header_u64(8), header_u64(16));
+        let mut root = Vec::new();
+        GzDecoder::new(&file[root_offset..root_offset + root_len])
+            .read_to_end(&mut root)
+            .unwrap();
+        assert_eq!(vec![2, 0, 4, 1, 1, 5, 6, 7, 1], root);
+
+        let data = &file[header_u64(56)..];
+        assert_eq!(b"secondfirst", data);
+        assert!(!dir.path().join("tiles.pmtiles.tmp").exists());
+    }
+}
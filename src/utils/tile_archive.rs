@@ -0,0 +1,242 @@
+use anyhow::{bail, Context};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use super::pmtiles::PMTilesWriter;
+
+enum Archive {
+    Tar(tar::Builder<BufWriter<File>>),
+    Zip(ZipWriter<BufWriter<File>>),
+    PMTiles(PMTilesWriter),
+}
+
+/// Single .tar, .zip or .pmtiles file tiles are streamed into instead of
+/// writing every tile to its own file, which saves inodes on huge tile sets.
+/// Tiles are stored uncompressed, as the image formats already are compressed.
+///
+/// An archive, which is dropped without being finished (because building the
+/// tiles failed), is removed instead of leaving a truncated file behind.
+pub struct TileArchive {
+    path: PathBuf,
+    archive: Mutex<Option<Archive>>,
+}
+
+impl TileArchive {
+    /// Creates the archive. Its format is picked from the extension of `path`.
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        if !matches!(extension, Some("tar") | Some("zip") | Some("pmtiles")) {
+            bail!("Tile archives have to be .tar, .zip or .pmtiles files");
+        }
+        if extension == Some("pmtiles") {
+            return Ok(TileArchive {
+                path: path.to_path_buf(),
+                archive: Mutex::new(Some(Archive::PMTiles(PMTilesWriter::create(path)?))),
+            });
+        }
+
+        let file = File::create(path)
+            .with_context(|| format!("Couldn't create archive {}", path.display()))?;
+        let file = BufWriter::new(file);
+        let archive = match extension {
+            Some("tar") => Archive::Tar(tar::Builder::new(file)),
+            _ => Archive::Zip(ZipWriter::new(file)),
+        };
+
+        Ok(TileArchive {
+            path: path.to_path_buf(),
+            archive: Mutex::new(Some(archive)),
+        })
+    }
+
+    /// Whether the archive at `path` can only hold tiles of a single format,
+    /// as PMTiles archives have one tile type.
+    pub fn holds_single_format(path: &Path) -> bool {
+        path.extension().and_then(|ext| ext.to_str()) == Some("pmtiles")
+    }
+
+    /// Adds the tile `z`/`x`/`y` to the archive. .tar and .zip archives store
+    /// it at `path`, which uses forward slashes.
+    pub fn append(
+        &self,
+        (z, x, y): (u8, u32, u32),
+        path: &str,
+        extension: &str,
+        data: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut archive = self.archive.lock().unwrap();
+
+        match archive.as_mut() {
+            Some(Archive::Tar(builder)) => {
+                let mtime = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_secs());
+
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(mtime);
+                builder.append_data(&mut header, path, data)?;
+            }
+            Some(Archive::Zip(zip)) => {
+                let options = FileOptions::default()
+                    .compression_method(CompressionMethod::Stored)
+                    .large_file(data.len() as u64 >= u32::MAX as u64);
+                zip.start_file(path, options)?;
+                zip.write_all(data)?;
+            }
+            Some(Archive::PMTiles(pmtiles)) => pmtiles.append(z, x, y, extension, data)?,
+            None => return Err("Archive is already finished".into()),
+        }
+
+        Ok(())
+    }
+
+    /// Writes the end of the archive. Nothing can be appended afterwards.
+    pub fn finish(&self) -> anyhow::Result<()> {
+        let archive = match self.archive.lock().unwrap().take() {
+            Some(archive) => archive,
+            None => return Ok(()),
+        };
+
+        let result = match archive {
+            Archive::Tar(builder) => builder
+                .into_inner()
+                .and_then(|mut file| file.flush())
+                .map_err(anyhow::Error::from),
+            Archive::Zip(mut zip) => zip
+                .finish()
+                .map_err(anyhow::Error::from)
+                .and_then(|mut file| Ok(file.flush()?)),
+            Archive::PMTiles(pmtiles) => return pmtiles.finish(),
+        };
+        if result.is_err() {
+            let _ = fs::remove_file(&self.path);
+        }
+
+        result
+    }
+}
+
+impl Drop for TileArchive {
+    fn drop(&mut self) {
+        let archive = match self.archive.get_mut() {
+            Ok(archive) => archive.take(),
+            Err(poisoned) => poisoned.into_inner().take(),
+        };
+
+        match archive {
+            Some(Archive::PMTiles(pmtiles)) => pmtiles.discard(),
+            Some(archive) => {
+                drop(archive);
+                let _ = fs::remove_file(&self.path);
+            }
+            None => return,
+        }
+        warning!("Removed incomplete archive {}", self.path.display());
+    }
+}
+
+impl std::fmt::Debug for TileArchive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TileArchive").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TileArchive;
+    use std::{fs::File, io::Read};
+    use tempdir::TempDir;
+
+    #[test]
+    fn writes_tar_archives() {
+        let dir = TempDir::new("meh-utils-rust-archive").unwrap();
+        let path = dir.path().join("tiles.tar");
+
+        let archive = TileArchive::create(&path).unwrap();
+        archive
+            .append((0, 0, 0), "0/0/0.png", "png", b"tile")
+            .unwrap();
+        archive.finish().unwrap();
+        assert!(archive
+            .append((0, 0, 1), "0/0/1.png", "png", b"tile")
+            .is_err());
+
+        let mut tar = tar::Archive::new(File::open(&path).unwrap());
+        let mut entries = tar.entries().unwrap();
+        let mut entry = entries.next().unwrap().unwrap();
+        let mut content = String::new();
+        entry.read_to_string(&mut content).unwrap();
+
+        assert_eq!("0/0/0.png", entry.path().unwrap().to_str().unwrap());
+        assert_eq!("tile", content);
+    }
+
+    #[test]
+    fn writes_zip_archives() {
+        let dir = TempDir::new("meh-utils-rust-archive").unwrap();
+        let path = dir.path().join("tiles.zip");
+
+        let archive = TileArchive::create(&path).unwrap();
+        archive
+            .append((0, 0, 0), "0/0/0.png", "png", b"tile")
+            .unwrap();
+        archive.finish().unwrap();
+
+        let mut zip = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut content = String::new();
+        zip.by_name("0/0/0.png")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+
+        assert_eq!("tile", content);
+    }
+
+    #[test]
+    fn removes_unfinished_archives() {
+        let dir = TempDir::new("meh-utils-rust-archive").unwrap();
+
+        for name in ["tiles.tar", "tiles.zip", "tiles.pmtiles"] {
+            let path = dir.path().join(name);
+            let archive = TileArchive::create(&path).unwrap();
+            archive
+                .append((0, 0, 0), "0/0/0.png", "png", b"tile")
+                .unwrap();
+            drop(archive);
+
+            assert!(!path.exists());
+        }
+        assert_eq!(0, std::fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn rejects_unknown_extensions() {
+        let dir = TempDir::new("meh-utils-rust-archive").unwrap();
+
+        assert!(TileArchive::create(&dir.path().join("tiles.7z")).is_err());
+    }
+
+    #[test]
+    fn writes_pmtiles_archives() {
+        let dir = TempDir::new("meh-utils-rust-archive").unwrap();
+        let path = dir.path().join("tiles.pmtiles");
+
+        let archive = TileArchive::create(&path).unwrap();
+        archive
+            .append((0, 0, 0), "0/0/0.png", "png", b"tile")
+            .unwrap();
+        archive.finish().unwrap();
+
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(b"PMTiles\x03", &file[..8]);
+        assert!(file.ends_with(b"tile"));
+    }
+}
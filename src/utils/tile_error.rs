@@ -18,6 +18,18 @@ impl TileError {
             original_error: original_error.into(),
         }
     }
+
+    pub fn col(&self) -> u32 {
+        self.col
+    }
+
+    pub fn row(&self) -> u32 {
+        self.row
+    }
+
+    pub fn original_error(&self) -> &Underlying {
+        &self.original_error
+    }
 }
 
 impl Display for TileError {
@@ -0,0 +1,187 @@
+use anyhow::{bail, Context};
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    ColorType, DynamicImage, GenericImageView, ImageEncoder,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    ops::RangeInclusive,
+    path::Path,
+    str::FromStr,
+};
+
+const JPEG_QUALITY: u8 = 85;
+
+/// Image formats tiles can be encoded in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TileFormat {
+    Png,
+    Jpeg,
+    /// lossless WebP
+    WebP,
+}
+
+impl TileFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TileFormat::Png => "png",
+            TileFormat::Jpeg => "jpg",
+            TileFormat::WebP => "webp",
+        }
+    }
+
+    /// Whether the format keeps every pixel (including alpha) as it is.
+    pub fn is_lossless(&self) -> bool {
+        !matches!(self, TileFormat::Jpeg)
+    }
+
+    pub fn encode(
+        &self,
+        file_path: &Path,
+        img: &DynamicImage,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut buf = BufWriter::new(File::create(file_path)?);
+        self.encode_to(&mut buf, img)?;
+        buf.flush()?;
+        Ok(())
+    }
+
+    pub fn encode_to(
+        &self,
+        out: &mut impl Write,
+        img: &DynamicImage,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match self {
+            TileFormat::Png => {
+                let (width, height) = img.dimensions();
+                PngEncoder::new(out).write_image(img.as_bytes(), width, height, img.color())?;
+            }
+            TileFormat::Jpeg => {
+                JpegEncoder::new_with_quality(out, JPEG_QUALITY).encode_image(&img.to_rgb8())?;
+            }
+            TileFormat::WebP => {
+                let rgba = img.to_rgba8();
+                WebPEncoder::new_lossless(out).encode(
+                    &rgba,
+                    rgba.width(),
+                    rgba.height(),
+                    ColorType::Rgba8,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for TileFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "png" => Ok(TileFormat::Png),
+            "jpg" | "jpeg" => Ok(TileFormat::Jpeg),
+            "webp" => Ok(TileFormat::WebP),
+            other => bail!("Unknown tile format '{}'", other),
+        }
+    }
+}
+
+/// Tile formats to use, optionally restricted to ranges of LODs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileFormats {
+    rules: Vec<(RangeInclusive<u8>, TileFormat)>,
+}
+
+impl TileFormats {
+    /// All formats, which are used for any LOD.
+    pub fn all(&self) -> Vec<TileFormat> {
+        let mut formats = Vec::new();
+        for (_, format) in &self.rules {
+            if !formats.contains(format) {
+                formats.push(*format);
+            }
+        }
+        formats
+    }
+
+    /// Formats tiles of `lod` are encoded in.
+    pub fn for_lod(&self, lod: u8) -> Vec<TileFormat> {
+        let mut formats = Vec::new();
+        for (range, format) in &self.rules {
+            if range.contains(&lod) && !formats.contains(format) {
+                formats.push(*format);
+            }
+        }
+        formats
+    }
+}
+
+/// Parses a comma separated list of formats like `png,webp`. Every format can
+/// be restricted to a range of LODs, like `0-4:jpeg,5-:png`.
+pub fn parse_formats(value: &str) -> anyhow::Result<TileFormats> {
+    let mut rules = Vec::new();
+
+    for rule in value.split(',') {
+        let rule = match rule.split_once(':') {
+            Some((range, format)) => (parse_lod_range(range)?, format.parse()?),
+            None => (0..=u8::MAX, rule.parse()?),
+        };
+        if !rules.contains(&rule) {
+            rules.push(rule);
+        }
+    }
+
+    Ok(TileFormats { rules })
+}
+
+/// Parses LOD ranges like `3`, `0-4`, `5-` or `-4`.
+fn parse_lod_range(value: &str) -> anyhow::Result<RangeInclusive<u8>> {
+    let parse = |lod: &str, default: u8| match lod.trim() {
+        "" => Ok(default),
+        lod => lod
+            .parse::<u8>()
+            .with_context(|| format!("Invalid LOD '{}'", lod)),
+    };
+
+    let range = match value.split_once('-') {
+        Some((min, max)) => parse(min, 0)?..=parse(max, u8::MAX)?,
+        None => {
+            let lod = parse(value, 0)?;
+            lod..=lod
+        }
+    };
+
+    if range.is_empty() {
+        bail!("Invalid LOD range '{}'", value);
+    }
+
+    Ok(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_formats, TileFormat};
+
+    #[test]
+    fn parses_format_lists() {
+        let formats = parse_formats("png, WEBP,png").unwrap();
+        assert_eq!(vec![TileFormat::Png, TileFormat::WebP], formats.all());
+        assert_eq!(vec![TileFormat::Png, TileFormat::WebP], formats.for_lod(7));
+        assert_eq!(vec![TileFormat::Jpeg], parse_formats("jpeg").unwrap().all());
+        assert!(parse_formats("png,gif").is_err());
+    }
+
+    #[test]
+    fn parses_formats_per_lod() {
+        let formats = parse_formats("0-4:jpeg,5-:png,-1:webp,3:png").unwrap();
+
+        assert_eq!(vec![TileFormat::Jpeg, TileFormat::WebP], formats.for_lod(0));
+        assert_eq!(vec![TileFormat::Jpeg, TileFormat::Png], formats.for_lod(3));
+        assert_eq!(vec![TileFormat::Jpeg], formats.for_lod(4));
+        assert_eq!(vec![TileFormat::Png], formats.for_lod(12));
+        assert!(parse_formats("4-2:png").is_err());
+        assert!(parse_formats("x:png").is_err());
+    }
+}
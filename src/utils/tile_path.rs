@@ -0,0 +1,269 @@
+use anyhow::bail;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+pub const DEFAULT_TILE_PATH: &str = "{z}/{x}/{y}.{ext}";
+
+/// LOD, column and row of a tile.
+pub type TileCoords = (u8, u32, u32);
+
+/// On-disk layout of a tile set, like `{z}/{x}/{y}.{ext}` or `tiles/{z}_{x}_{y}.png`.
+/// `{ext}` is replaced with the extension of the tile format.
+#[derive(Debug, Clone)]
+pub struct TilePathTemplate {
+    template: String,
+}
+
+impl TilePathTemplate {
+    pub fn new(template: &str) -> anyhow::Result<Self> {
+        for placeholder in ["{z}", "{x}", "{y}"] {
+            if !template.contains(placeholder) {
+                bail!("Tile path template is missing {}", placeholder);
+            }
+        }
+
+        if !Path::new(template)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            bail!("Tile path template has to stay within the output directory");
+        }
+
+        Ok(TilePathTemplate {
+            template: template.to_owned(),
+        })
+    }
+
+    pub fn has_extension_placeholder(&self) -> bool {
+        self.template.contains("{ext}")
+    }
+
+    /// Extension written out in the template, like `png` in `{z}/{x}/{y}.png`.
+    pub fn fixed_extension(&self) -> Option<&str> {
+        Path::new(&self.template)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .filter(|ext| !ext.contains('{'))
+    }
+
+    /// Template of the tile URLs relative to the tile set, as used in tile.json.
+    pub fn url_template(&self, ext: &str) -> String {
+        self.template.replace('\\', "/").replace("{ext}", ext)
+    }
+
+    /// Path of a tile within an archive, always with forward slashes.
+    pub fn archive_path(&self, z: u8, x: u32, y: u32, ext: &str) -> String {
+        self.url_template(ext)
+            .replace("{z}", &z.to_string())
+            .replace("{x}", &x.to_string())
+            .replace("{y}", &y.to_string())
+    }
+
+    pub fn path(&self, set_base_path: &Path, z: u8, x: u32, y: u32, ext: &str) -> PathBuf {
+        set_base_path.join(
+            self.template
+                .replace("{z}", &z.to_string())
+                .replace("{x}", &x.to_string())
+                .replace("{y}", &y.to_string())
+                .replace("{ext}", ext),
+        )
+    }
+
+    /// Coordinates of the tile at `relative_path` (with forward slashes), if
+    /// the path matches the template.
+    pub fn parse(&self, relative_path: &str) -> Option<TileCoords> {
+        let template = self.template.replace('\\', "/");
+        let mut coords = [None; 3];
+        if !match_template(&template, relative_path, &mut coords) {
+            return None;
+        }
+
+        match coords {
+            [Some(z), Some(x), Some(y)] => Some((u8::try_from(z).ok()?, x, y)),
+            _ => None,
+        }
+    }
+
+    /// All tiles below `set_base_path` matching the template.
+    pub fn find_tiles(&self, set_base_path: &Path) -> io::Result<Vec<(TileCoords, PathBuf)>> {
+        let mut tiles = Vec::new();
+        let mut dirs = vec![set_base_path.to_path_buf()];
+
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+
+                let relative = path
+                    .strip_prefix(set_base_path)
+                    .ok()
+                    .and_then(|relative| relative.to_str())
+                    .map(|relative| relative.replace('\\', "/"));
+                if let Some(coords) = relative.and_then(|relative| self.parse(&relative)) {
+                    tiles.push((coords, path));
+                }
+            }
+        }
+
+        tiles.sort();
+
+        Ok(tiles)
+    }
+
+    /// Removes the tiles below `set_base_path` for which `filter` is true,
+    /// together with the directories left empty. Returns the removed tiles.
+    pub fn remove_tiles(
+        &self,
+        set_base_path: &Path,
+        filter: impl Fn(u8, u32, u32) -> bool,
+    ) -> io::Result<Vec<TileCoords>> {
+        let mut removed = Vec::new();
+
+        for ((z, x, y), path) in self.find_tiles(set_base_path)? {
+            if !filter(z, x, y) {
+                continue;
+            }
+
+            fs::remove_file(&path)?;
+            removed.push((z, x, y));
+
+            let mut parent = path.parent();
+            while let Some(dir) = parent.filter(|dir| *dir != set_base_path) {
+                if fs::read_dir(dir)?.next().is_some() {
+                    break;
+                }
+                fs::remove_dir(dir)?;
+                parent = dir.parent();
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Matches `path` against the rest of a template. `{z}`, `{x}` and `{y}` match
+/// numbers, `{ext}` matches a file extension. Placeholders used more than once
+/// have to match the same number.
+fn match_template(template: &str, path: &str, coords: &mut [Option<u32>; 3]) -> bool {
+    if template.is_empty() {
+        return path.is_empty();
+    }
+
+    for (index, placeholder) in ["{z}", "{x}", "{y}"].iter().enumerate() {
+        if let Some(rest) = template.strip_prefix(placeholder) {
+            let digits = path.bytes().take_while(u8::is_ascii_digit).count();
+            for len in 1..=digits {
+                let value = match path[..len].parse::<u32>() {
+                    Ok(value) => value,
+                    Err(_) => return false,
+                };
+                if coords[index].is_some_and(|existing| existing != value) {
+                    continue;
+                }
+
+                let previous = coords[index].replace(value);
+                if match_template(rest, &path[len..], coords) {
+                    return true;
+                }
+                coords[index] = previous;
+            }
+            return false;
+        }
+    }
+
+    if let Some(rest) = template.strip_prefix("{ext}") {
+        let chars = path.bytes().take_while(u8::is_ascii_alphanumeric).count();
+        return (1..=chars).any(|len| match_template(rest, &path[len..], coords));
+    }
+
+    let mut chars = template.chars();
+    let first = chars.next().unwrap();
+    match path.strip_prefix(first) {
+        Some(path_rest) => match_template(chars.as_str(), path_rest, coords),
+        None => false,
+    }
+}
+
+impl Default for TilePathTemplate {
+    fn default() -> Self {
+        TilePathTemplate::new(DEFAULT_TILE_PATH).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TilePathTemplate;
+    use std::path::Path;
+
+    #[test]
+    fn fills_in_placeholders() {
+        let base = Path::new("out");
+
+        assert_eq!(
+            Path::new("out/3/1/2.webp"),
+            TilePathTemplate::default().path(base, 3, 1, 2, "webp")
+        );
+        assert_eq!(
+            Path::new("out/tiles/3/1_2.png"),
+            TilePathTemplate::new("tiles/{z}/{x}_{y}.png")
+                .unwrap()
+                .path(base, 3, 1, 2, "webp")
+        );
+    }
+
+    #[test]
+    fn builds_url_templates() {
+        assert_eq!(
+            "{z}/{x}/{y}.png",
+            TilePathTemplate::default().url_template("png")
+        );
+    }
+
+    #[test]
+    fn rejects_incomplete_templates() {
+        assert!(TilePathTemplate::new("{z}/{x}.png").is_err());
+        assert!(TilePathTemplate::new("/{z}/{x}/{y}.png").is_err());
+        assert!(TilePathTemplate::new("../{z}/{x}/{y}.png").is_err());
+        assert!(TilePathTemplate::new("tiles/../../{z}/{x}/{y}.png").is_err());
+        assert!(TilePathTemplate::new("./{z}/{x}/{y}.png").is_err());
+    }
+
+    #[test]
+    fn parses_tile_paths() {
+        let default = TilePathTemplate::default();
+        assert_eq!(Some((3, 1, 12)), default.parse("3/1/12.webp"));
+        assert_eq!(None, default.parse("3/1/12"));
+        assert_eq!(None, default.parse("2024/report.pdf"));
+        assert_eq!(None, default.parse("300/1/2.png"));
+
+        let flat = TilePathTemplate::new("tiles/{z}_{x}_{y}.png").unwrap();
+        assert_eq!(Some((12, 3, 4)), flat.parse("tiles/12_3_4.png"));
+        assert_eq!(None, flat.parse("tiles/12_3_4.jpg"));
+        assert_eq!(None, flat.parse("12_3_4.png"));
+    }
+
+    #[test]
+    fn removes_tiles_and_empty_directories() {
+        let dir = tempdir::TempDir::new("meh-utils-rust-tile-path").unwrap();
+        let template = TilePathTemplate::new("tiles/{z}/{x}-{y}.{ext}").unwrap();
+        for tile in ["tiles/0/0-0.png", "tiles/1/0-1.png", "tiles/1/1-1.jpg"] {
+            let path = dir.path().join(tile);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        std::fs::write(dir.path().join("tiles/1/notes.txt"), "").unwrap();
+
+        let removed = template.remove_tiles(dir.path(), |z, _, _| z == 0).unwrap();
+
+        assert_eq!(vec![(0, 0, 0)], removed);
+        assert!(!dir.path().join("tiles/0").exists());
+        assert_eq!(2, template.find_tiles(dir.path()).unwrap().len());
+
+        template.remove_tiles(dir.path(), |_, _, _| true).unwrap();
+        assert!(dir.path().join("tiles/1/notes.txt").exists());
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::to_string_pretty;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use super::{tile_path::TileCoords, TileError};
+
+/// Number of tiles per LOD and bytes written by a [`TileWriter`](super::TileWriter),
+/// and the tiles which failed to be written with `--keep-going`.
+#[derive(Debug, Default)]
+pub struct TileStats {
+    tiles: Mutex<BTreeMap<u8, u64>>,
+    bytes: AtomicU64,
+    failures: Mutex<Vec<FailedTile>>,
+}
+
+/// Entry of the list written by [`TileStats::write_failures`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FailedTile {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32,
+    pub error: String,
+}
+
+impl fmt::Display for FailedTile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tile {}/{}/{}: {}", self.z, self.x, self.y, self.error)
+    }
+}
+
+impl TileStats {
+    /// Counts one tile of LOD `z`, which took `bytes` over all of its formats.
+    pub fn record(&self, z: u8, bytes: u64) {
+        *self.tiles.lock().unwrap().entry(z).or_insert(0) += 1;
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_failure(&self, z: u8, error: &TileError) {
+        self.failures.lock().unwrap().push(FailedTile {
+            z,
+            x: error.col(),
+            y: error.row(),
+            error: error.original_error().to_string(),
+        });
+    }
+
+    /// Failed tiles ordered by LOD, column and row.
+    pub fn failures(&self) -> Vec<FailedTile> {
+        let mut failures = self.failures.lock().unwrap().clone();
+        failures.sort();
+        failures
+    }
+
+    /// Fails with a list of all failed tiles, if there are any.
+    pub fn check_failures(&self) -> anyhow::Result<()> {
+        let failures = self.failures();
+        if failures.is_empty() {
+            return Ok(());
+        }
+
+        let lines = failures.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+        bail!("{} tiles failed:\n{}", failures.len(), lines.join("\n"))
+    }
+
+    /// Writes the failed tiles as JSON array of `{"z", "x", "y", "error"}`
+    /// objects, so they can be picked up by scripts.
+    pub fn write_failures(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, to_string_pretty(&self.failures())?)?;
+        Ok(())
+    }
+
+    /// Reads a list written by [`write_failures`](Self::write_failures),
+    /// e.g. to build just those tiles again.
+    pub fn read_failures(path: &Path) -> anyhow::Result<HashSet<TileCoords>> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        let failures: Vec<FailedTile> = serde_json::from_str(&json)
+            .with_context(|| format!("{} is no list of failed tiles", path.display()))?;
+
+        Ok(failures.into_iter().map(|f| (f.z, f.x, f.y)).collect())
+    }
+
+    pub fn tiles(&self) -> u64 {
+        self.tiles.lock().unwrap().values().sum()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Display for TileStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Wrote {} tiles ({})",
+            self.tiles(),
+            format_bytes(self.bytes())
+        )?;
+
+        let tiles = self.tiles.lock().unwrap();
+        if !tiles.is_empty() {
+            let per_lod = tiles
+                .iter()
+                .map(|(z, count)| format!("{}: {}", z, count))
+                .collect::<Vec<_>>();
+            write!(f, ", per LOD {}", per_lod.join(", "))?;
+        }
+
+        let failures = self.failures.lock().unwrap().len();
+        if failures > 0 {
+            write!(f, ", {} failed", failures)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_bytes, TileStats};
+    use crate::utils::TileError;
+    use serde_json::{json, Value};
+    use std::collections::HashSet;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn counts_tiles_per_lod() {
+        let stats = TileStats::default();
+        stats.record(1, 1000);
+        stats.record(0, 500);
+        stats.record(1, 1000);
+
+        assert_eq!(3, stats.tiles());
+        assert_eq!(2500, stats.bytes());
+        assert_eq!(
+            "Wrote 3 tiles (2.4 KiB), per LOD 0: 1, 1: 2",
+            stats.to_string()
+        );
+    }
+
+    #[test]
+    fn lists_failures() {
+        let stats = TileStats::default();
+        assert!(stats.check_failures().is_ok());
+
+        stats.record_failure(2, &TileError::new(1, 3, "disk full"));
+        stats.record(2, 10);
+
+        assert!(stats.to_string().ends_with(", 1 failed"));
+        let error = stats.check_failures().unwrap_err().to_string();
+        assert_eq!("1 tiles failed:\nTile 2/1/3: disk full", error);
+    }
+
+    #[test]
+    fn writes_failures_as_json() {
+        let dir = TempDir::new("meh-utils-rust-failures").unwrap();
+        let path = dir.path().join("failed.json");
+        let stats = TileStats::default();
+        stats.record_failure(3, &TileError::new(4, 5, "timed out"));
+        stats.record_failure(1, &TileError::new(0, 1, "disk full"));
+
+        stats.write_failures(&path).unwrap();
+
+        let json: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            json!([
+                { "z": 1, "x": 0, "y": 1, "error": "disk full" },
+                { "z": 3, "x": 4, "y": 5, "error": "timed out" }
+            ]),
+            json
+        );
+
+        let tiles = TileStats::read_failures(&path).unwrap();
+        assert_eq!(HashSet::from([(1, 0, 1), (3, 4, 5)]), tiles);
+    }
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!("512 B", format_bytes(512));
+        assert_eq!("1.5 MiB", format_bytes(1024 * 1536));
+    }
+}
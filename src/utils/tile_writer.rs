@@ -0,0 +1,413 @@
+use anyhow::bail;
+use image::{DynamicImage, ImageError};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    error::Error,
+    fs::create_dir_all,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use super::tile_format::TileFormat;
+use super::tile_path::TileCoords;
+use super::{
+    TileArchive, TileError, TileFormats, TilePathTemplate, TileStats, Watermark, WriteLimiter,
+};
+
+/// How often writing a tile file is tried, if it fails with a transient error.
+const WRITE_ATTEMPTS: u32 = 3;
+
+/// Writes tiles of a tile set to disk, once per format configured for their LOD.
+#[derive(Debug, Clone)]
+pub struct TileWriter {
+    set_base_path: PathBuf,
+    tile_path: TilePathTemplate,
+    formats: TileFormats,
+    limiter: Option<Arc<WriteLimiter>>,
+    /// watermark and the highest LOD it is stamped on
+    watermark: Option<(Watermark, u8)>,
+    encoder_threads: Option<usize>,
+    archive: Option<Arc<TileArchive>>,
+    stats: Arc<TileStats>,
+    keep_going: bool,
+    only_tiles: Option<Arc<HashSet<TileCoords>>>,
+}
+
+impl TileWriter {
+    pub fn new(
+        set_base_path: &Path,
+        tile_path: TilePathTemplate,
+        formats: TileFormats,
+    ) -> anyhow::Result<Self> {
+        if formats.all().is_empty() {
+            bail!("At least one tile format is needed");
+        }
+
+        if !tile_path.has_extension_placeholder() {
+            let all = formats.all();
+            if all.len() > 1 {
+                bail!("Tile path template needs an {{ext}} placeholder to write multiple formats");
+            }
+
+            let format = all[0];
+            let fixed_format = tile_path
+                .fixed_extension()
+                .and_then(|ext| ext.parse::<TileFormat>().ok());
+            if fixed_format != Some(format) {
+                bail!(
+                    "Tile path template has to end in .{} or .{{ext}} to write {:?} tiles",
+                    format.extension(),
+                    format
+                );
+            }
+        }
+
+        Ok(TileWriter {
+            set_base_path: set_base_path.to_owned(),
+            tile_path,
+            formats,
+            limiter: None,
+            watermark: None,
+            encoder_threads: None,
+            archive: None,
+            stats: Arc::new(TileStats::default()),
+            keep_going: false,
+            only_tiles: None,
+        })
+    }
+
+    /// Stamps the watermark on all tiles up to `max_lod`.
+    pub fn watermark(mut self, watermark: Watermark, max_lod: u8) -> Self {
+        self.watermark = Some((watermark, max_lod));
+        self
+    }
+
+    /// Makes sure there is a tile format for every LOD from `min_lod` to `max_lod`.
+    pub fn check_lod_range(&self, min_lod: u8, max_lod: u8) -> anyhow::Result<()> {
+        for lod in min_lod..=max_lod {
+            if self.formats.for_lod(lod).is_empty() {
+                bail!("No tile format configured for LOD {}", lod);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// URL templates of the tiles relative to the tile set, one per format
+    /// which is written for every LOD from `min_lod` to `max_lod`. TileJSON
+    /// clients treat them as mirrors of the whole tile set.
+    pub fn tile_urls(&self, min_lod: u8, max_lod: u8) -> anyhow::Result<Vec<String>> {
+        let urls: Vec<_> = self
+            .formats
+            .all()
+            .into_iter()
+            .filter(|format| {
+                (min_lod..=max_lod).all(|lod| self.formats.for_lod(lod).contains(format))
+            })
+            .map(|format| self.tile_path.url_template(format.extension()))
+            .collect();
+
+        if urls.is_empty() {
+            bail!("TileJSON 3.0.0 needs a tile format, which is used for all LODs");
+        }
+
+        Ok(urls)
+    }
+
+    pub fn tile_path(&self) -> &TilePathTemplate {
+        &self.tile_path
+    }
+
+    /// Limits how many tiles are written at the same time, which keeps
+    /// the number of open file handles low on huge tile sets.
+    pub fn limit_concurrent_writes(mut self, max_concurrent_writes: usize) -> Self {
+        self.limiter = Some(Arc::new(WriteLimiter::new(max_concurrent_writes)));
+        self
+    }
+
+    /// Encodes tiles on this many dedicated threads instead of on the rayon
+    /// pool, which resizes them (see `write_on_encoder_threads`).
+    pub fn encode_on_threads(mut self, threads: usize) -> Self {
+        self.encoder_threads = Some(threads);
+        self
+    }
+
+    pub fn encoder_threads(&self) -> Option<usize> {
+        self.encoder_threads
+    }
+
+    /// Streams all tiles into the archive at `path` instead of writing them
+    /// as single files. Archives are always written from scratch, so this
+    /// fails if the writer is restricted with [`TileWriter::only_tiles`].
+    pub fn archive(mut self, path: &Path) -> anyhow::Result<Self> {
+        if self.only_tiles.is_some() {
+            bail!("--only-tiles can't be combined with --archive");
+        }
+        if TileArchive::holds_single_format(path) && self.formats.all().len() > 1 {
+            bail!("{} can only hold tiles of a single format", path.display());
+        }
+
+        self.archive = Some(Arc::new(TileArchive::create(path)?));
+        Ok(self)
+    }
+
+    /// Records tiles which fail to be written and carries on with the others,
+    /// instead of aborting. Check for them with [`TileStats::check_failures`].
+    pub fn keep_going(mut self) -> Self {
+        self.keep_going = true;
+        self
+    }
+
+    /// Only writes these tiles, e.g. the ones which failed in a previous run
+    /// (see [`TileStats::read_failures`]).
+    pub fn only_tiles(mut self, tiles: HashSet<TileCoords>) -> Self {
+        self.only_tiles = Some(Arc::new(tiles));
+        self
+    }
+
+    /// Whether the tile is to be written. Tile sets skip the others before
+    /// rendering them.
+    pub fn includes_tile(&self, x: u32, y: u32, z: u8) -> bool {
+        self.only_tiles
+            .as_ref()
+            .is_none_or(|tiles| tiles.contains(&(z, x, y)))
+    }
+
+    /// Tiles and bytes written so far (by this writer and its clones).
+    pub fn stats(&self) -> &TileStats {
+        &self.stats
+    }
+
+    /// Completes the archive, if tiles are written into one.
+    pub fn finish(&self) -> anyhow::Result<()> {
+        match &self.archive {
+            Some(archive) => archive.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// [`write`](Self::write)s a tile and only fails, if building the tile set
+    /// has to be aborted because of it.
+    pub fn write_tile(&self, img: &DynamicImage, x: u32, y: u32, z: u8) -> Result<(), TileError> {
+        let error = match self.write(img, x, y, z) {
+            Ok(()) => return Ok(()),
+            Err(e) => TileError::new(x, y, e),
+        };
+
+        if !self.keep_going {
+            return Err(error);
+        }
+        warning!("LOD {}, {}", z, error);
+        self.stats.record_failure(z, &error);
+
+        Ok(())
+    }
+
+    pub fn write(
+        &self,
+        img: &DynamicImage,
+        x: u32,
+        y: u32,
+        z: u8,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let formats = self.formats.for_lod(z);
+        if formats.is_empty() {
+            return Err(format!("No tile format configured for LOD {}", z).into());
+        }
+
+        let img = match &self.watermark {
+            Some((watermark, max_lod)) if z <= *max_lod => Cow::Owned(watermark.apply(img)),
+            _ => Cow::Borrowed(img),
+        };
+
+        let mut bytes = 0;
+        for format in formats {
+            let _span = tracing::trace_span!("encode", format = format.extension()).entered();
+
+            if let Some(archive) = &self.archive {
+                let mut data = Vec::new();
+                format.encode_to(&mut data, &img)?;
+                archive.append(
+                    (z, x, y),
+                    &self.tile_path.archive_path(z, x, y, format.extension()),
+                    format.extension(),
+                    &data,
+                )?;
+                bytes += data.len() as u64;
+                continue;
+            }
+
+            let file_path = self
+                .tile_path
+                .path(&self.set_base_path, z, x, y, format.extension());
+            let _permit = self.limiter.as_ref().map(|limiter| limiter.acquire());
+            with_retries(|| {
+                if let Some(dir) = file_path.parent() {
+                    create_dir_all(dir)?;
+                }
+                format.encode(&file_path, &img)
+            })?;
+            bytes += file_path.metadata()?.len();
+        }
+        self.stats.record(z, bytes);
+
+        Ok(())
+    }
+}
+
+/// Calls `write` again (after a short pause), as long as it fails with a
+/// transient IO error like EAGAIN or a hiccup of a network file system.
+fn with_retries(
+    mut write: impl FnMut() -> Result<(), Box<dyn Error + Send + Sync>>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut attempt = 1;
+    loop {
+        match write() {
+            Err(e) if attempt < WRITE_ATTEMPTS && is_transient(&*e) => {
+                thread::sleep(Duration::from_millis(50 << attempt));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient(error: &(dyn Error + Send + Sync + 'static)) -> bool {
+    let io_error = match error.downcast_ref::<ImageError>() {
+        Some(ImageError::IoError(e)) => Some(e),
+        _ => error.downcast_ref::<io::Error>(),
+    };
+
+    matches!(
+        io_error.map(io::Error::kind),
+        Some(
+            io::ErrorKind::WouldBlock
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::ResourceBusy
+        )
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{with_retries, TileWriter, WRITE_ATTEMPTS};
+    use crate::utils::{parse_formats, TilePathTemplate};
+    use std::collections::HashSet;
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    fn writer(tile_path: &str, formats: &str) -> anyhow::Result<TileWriter> {
+        TileWriter::new(
+            Path::new("out"),
+            TilePathTemplate::new(tile_path).unwrap(),
+            parse_formats(formats).unwrap(),
+        )
+    }
+
+    #[test]
+    fn checks_extension_of_tile_path() {
+        assert!(writer("{z}/{x}/{y}.{ext}", "png,webp").is_ok());
+        assert!(writer("{z}/{x}/{y}.png", "png").is_ok());
+        assert!(writer("{z}/{x}/{y}.jpeg", "jpeg").is_ok());
+
+        assert!(writer("{z}/{x}/{y}.png", "jpeg").is_err());
+        assert!(writer("{z}/{x}/{y}.png", "0-2:png,3-:webp").is_err());
+        assert!(writer("{z}/{x}/{y}", "png").is_err());
+    }
+
+    #[test]
+    fn rejects_multiple_formats_in_pmtiles() {
+        let dir = tempdir::TempDir::new("meh-utils-rust-writer").unwrap();
+        let path = dir.path().join("tiles.pmtiles");
+
+        assert!(writer("{z}/{x}/{y}.{ext}", "png,webp")
+            .unwrap()
+            .archive(&path)
+            .is_err());
+        assert!(writer("{z}/{x}/{y}.{ext}", "0-2:jpeg,3-:png")
+            .unwrap()
+            .archive(&path)
+            .is_err());
+        assert!(!dir.path().join("tiles.pmtiles.tmp").exists());
+
+        assert!(writer("{z}/{x}/{y}.{ext}", "png")
+            .unwrap()
+            .archive(&dir.path().join("tiles.zip"))
+            .is_ok());
+        assert!(writer("{z}/{x}/{y}.{ext}", "png,webp")
+            .unwrap()
+            .archive(&dir.path().join("tiles.tar"))
+            .is_ok());
+    }
+
+    #[test]
+    fn keeps_archive_when_only_writing_some_tiles() {
+        let dir = tempdir::TempDir::new("meh-utils-rust-writer").unwrap();
+        let path = dir.path().join("tiles.zip");
+        fs::write(&path, "previous run").unwrap();
+
+        assert!(writer("{z}/{x}/{y}.{ext}", "png")
+            .unwrap()
+            .only_tiles(HashSet::from([(0, 0, 0)]))
+            .archive(&path)
+            .is_err());
+        assert_eq!("previous run", fs::read_to_string(&path).unwrap());
+    }
+
+    #[test]
+    fn lists_urls_of_formats_covering_all_lods() {
+        assert_eq!(
+            vec!["{z}/{x}/{y}.png", "{z}/{x}/{y}.webp"],
+            writer("{z}/{x}/{y}.{ext}", "png,webp")
+                .unwrap()
+                .tile_urls(0, 5)
+                .unwrap()
+        );
+        assert_eq!(
+            vec!["{z}/{x}/{y}.png"],
+            writer("{z}/{x}/{y}.{ext}", "0-2:jpeg,0-:png")
+                .unwrap()
+                .tile_urls(0, 5)
+                .unwrap()
+        );
+        assert!(writer("{z}/{x}/{y}.{ext}", "0-2:jpeg,3-:png")
+            .unwrap()
+            .tile_urls(0, 5)
+            .is_err());
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let mut attempts = 0;
+
+        let result = with_retries(|| {
+            attempts += 1;
+            if attempts < WRITE_ATTEMPTS {
+                return Err(io::Error::from(io::ErrorKind::WouldBlock).into());
+            }
+            Ok(())
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(WRITE_ATTEMPTS, attempts);
+    }
+
+    #[test]
+    fn gives_up_on_other_errors() {
+        let mut attempts = 0;
+
+        let result = with_retries(|| {
+            attempts += 1;
+            Err(io::Error::from(io::ErrorKind::PermissionDenied).into())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(1, attempts);
+    }
+}
@@ -0,0 +1,280 @@
+use anyhow::Context;
+use image::{
+    imageops::{overlay, FilterType},
+    io::Reader as ImageReader,
+    DynamicImage, GenericImageView, Rgba, RgbaImage,
+};
+use std::path::Path;
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const TEXT_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const TEXT_BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 128]);
+/// logos are scaled down to at most this share of the image width
+const MAX_LOGO_SHARE: f32 = 0.25;
+
+/// Attribution stamped into the bottom right corner of images.
+#[derive(Debug, Clone)]
+pub enum Watermark {
+    /// rendered with a built-in 5x7 pixel font (ASCII letters, digits and
+    /// common punctuation; other characters are shown as `?`)
+    Text(String),
+    Logo(RgbaImage),
+}
+
+impl Watermark {
+    pub fn logo(path: &Path) -> anyhow::Result<Self> {
+        let logo = ImageReader::open(path)
+            .with_context(|| format!("Couldn't open watermark logo {}", path.display()))?
+            .decode()?;
+
+        Ok(Watermark::Logo(logo.to_rgba8()))
+    }
+
+    pub fn apply(&self, img: &DynamicImage) -> DynamicImage {
+        let mut buffer = img.to_rgba8();
+
+        match self {
+            Watermark::Text(text) => stamp_text(&mut buffer, text),
+            Watermark::Logo(logo) => stamp_logo(&mut buffer, logo),
+        }
+
+        DynamicImage::ImageRgba8(buffer)
+    }
+}
+
+fn stamp_logo(img: &mut RgbaImage, logo: &RgbaImage) {
+    let (width, height) = img.dimensions();
+    let margin = (width / 64).max(1);
+
+    let max_width = ((width as f32 * MAX_LOGO_SHARE) as u32).max(1);
+    let resized;
+    let logo = if logo.width() > max_width {
+        let logo_height = (logo.height() * max_width / logo.width()).max(1);
+        resized = image::imageops::resize(logo, max_width, logo_height, FilterType::Triangle);
+        &resized
+    } else {
+        logo
+    };
+
+    let x = width as i64 - logo.width() as i64 - margin as i64;
+    let y = height as i64 - logo.height() as i64 - margin as i64;
+    overlay(img, logo, x, y);
+}
+
+fn stamp_text(img: &mut RgbaImage, text: &str) {
+    let (width, height) = img.dimensions();
+    let scale = (width / 320).max(1);
+    let padding = 2 * scale;
+    let advance = (GLYPH_WIDTH + 1) * scale;
+
+    let chars = text.chars().count() as u32;
+    if chars == 0 {
+        return;
+    }
+
+    let box_width = chars * advance - scale + 2 * padding;
+    let box_height = GLYPH_HEIGHT * scale + 2 * padding;
+    let left = width as i64 - box_width as i64 - padding as i64;
+    let top = height as i64 - box_height as i64 - padding as i64;
+
+    let background = RgbaImage::from_pixel(box_width, box_height, TEXT_BACKGROUND);
+    overlay(img, &background, left, top);
+
+    for (index, c) in text.chars().enumerate() {
+        let glyph_left = left + (padding + index as u32 * advance) as i64;
+        let glyph_top = top + padding as i64;
+
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let x = glyph_left + (col * scale + dx) as i64;
+                        let y = glyph_top + (row as u32 * scale + dy) as i64;
+                        if x >= 0 && y >= 0 && img.in_bounds(x as u32, y as u32) {
+                            img.put_pixel(x as u32, y as u32, TEXT_COLOR);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Rows of the 5x7 glyph of `c`, the leftmost pixel is the highest bit.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        'A' => [
+            0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+        'B' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+        ],
+        'C' => [
+            0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+        ],
+        'D' => [
+            0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110,
+        ],
+        'E' => [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+        ],
+        'F' => [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+        'G' => [
+            0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+        ],
+        'H' => [
+            0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+        'I' => [
+            0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+        'J' => [
+            0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+        ],
+        'K' => [
+            0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+        ],
+        'L' => [
+            0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+        ],
+        'M' => [
+            0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+        ],
+        'N' => [
+            0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+        ],
+        'O' => [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+        'P' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+        'Q' => [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+        ],
+        'R' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+        ],
+        'S' => [
+            0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+        ],
+        'T' => [
+            0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+        'U' => [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+        'V' => [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+        ],
+        'W' => [
+            0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+        ],
+        'X' => [
+            0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+        ],
+        'Y' => [
+            0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100,
+        ],
+        'Z' => [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+        ],
+        '0' => [
+            0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+        ],
+        '1' => [
+            0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+        '2' => [
+            0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+        ],
+        '3' => [
+            0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+        ],
+        '4' => [
+            0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+        ],
+        '5' => [
+            0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+        ],
+        '6' => [
+            0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+        ],
+        '7' => [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+        ],
+        '8' => [
+            0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+        ],
+        '9' => [
+            0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+        ],
+        ' ' => [0; 7],
+        '.' => [0, 0, 0, 0, 0, 0b01100, 0b01100],
+        ',' => [0, 0, 0, 0, 0b01100, 0b00100, 0b01000],
+        '-' => [0, 0, 0, 0b11111, 0, 0, 0],
+        ':' => [0, 0b01100, 0b01100, 0, 0b01100, 0b01100, 0],
+        '/' => [
+            0b00001, 0b00010, 0b00010, 0b00100, 0b01000, 0b01000, 0b10000,
+        ],
+        '(' => [
+            0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010,
+        ],
+        ')' => [
+            0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000,
+        ],
+        '&' => [
+            0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101,
+        ],
+        '\'' => [0b00100, 0b00100, 0b01000, 0, 0, 0, 0],
+        '!' => [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0, 0b00100],
+        '_' => [0, 0, 0, 0, 0, 0, 0b11111],
+        '@' => [
+            0b01110, 0b10001, 0b10111, 0b10101, 0b10111, 0b10000, 0b01111,
+        ],
+        '©' => [
+            0b01110, 0b10001, 0b10111, 0b10100, 0b10111, 0b10001, 0b01110,
+        ],
+        _ => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0, 0b00100],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Watermark;
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    fn blank() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(64, 32, Rgba([0, 128, 0, 255])))
+    }
+
+    #[test]
+    fn stamps_text_into_bottom_right_corner() {
+        let stamped = Watermark::Text(String::from("(c) 1"))
+            .apply(&blank())
+            .to_rgba8();
+
+        assert_eq!(Rgba([0, 128, 0, 255]), *stamped.get_pixel(0, 0));
+        assert_eq!(Rgba([0, 128, 0, 255]), *stamped.get_pixel(63, 31));
+        // second row of the "1" glyph
+        assert_eq!(Rgba([255, 255, 255, 255]), *stamped.get_pixel(56, 22));
+    }
+
+    #[test]
+    fn scales_large_logos_down() {
+        let logo = RgbaImage::from_pixel(64, 64, Rgba([255, 0, 0, 255]));
+        let stamped = Watermark::Logo(logo).apply(&blank()).to_rgba8();
+
+        // logo is 16x16 with a margin of one pixel
+        assert_eq!(Rgba([255, 0, 0, 255]), *stamped.get_pixel(62, 30));
+        assert_eq!(Rgba([255, 0, 0, 255]), *stamped.get_pixel(47, 15));
+        assert_eq!(Rgba([0, 128, 0, 255]), *stamped.get_pixel(46, 15));
+    }
+}
@@ -0,0 +1,246 @@
+use std::f64::consts::PI;
+
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::{write_on_encoder_threads, TileWriter, TILE_SIZE_IN_PX};
+use crate::metajson::MetaJSON;
+
+const EARTH_RADIUS: f64 = 6378137.0;
+
+/*
+    Arma maps have no real geographic location. We anchor the center of the
+    world at the latitude/longitude configured for the map (Arma uses
+    inverted latitudes, so a configured latitude of -40 means 40°N) and
+    treat the world as a local tangent plane around that point. That is
+    plenty accurate for worlds that are a few dozen kilometers wide.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct Georeference {
+    world_size: f64,
+    center_lon: f64,
+    center_lat: f64,
+}
+
+impl Georeference {
+    pub fn new(world_size: f64, center_lon: f64, center_lat: f64) -> Self {
+        Georeference {
+            world_size,
+            center_lon,
+            center_lat,
+        }
+    }
+
+    pub fn from_meta(meta: &MetaJSON) -> Self {
+        Georeference::new(
+            meta.world_size as f64,
+            meta.longitude as f64,
+            -meta.latitude as f64,
+        )
+    }
+
+    /// Converts in-game meters (x east, y north) to longitude/latitude in degrees.
+    pub fn lon_lat(&self, x: f64, y: f64) -> (f64, f64) {
+        let half = self.world_size / 2.0;
+        let lat = self.center_lat + ((y - half) / EARTH_RADIUS).to_degrees();
+        let lon = self.center_lon
+            + ((x - half) / (EARTH_RADIUS * self.center_lat.to_radians().cos())).to_degrees();
+
+        (lon, lat)
+    }
+
+    /// Converts longitude/latitude in degrees to in-game meters (x east, y north).
+    pub fn world_pos(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let half = self.world_size / 2.0;
+        let x = (lon - self.center_lon).to_radians()
+            * EARTH_RADIUS
+            * self.center_lat.to_radians().cos()
+            + half;
+        let y = (lat - self.center_lat).to_radians() * EARTH_RADIUS + half;
+
+        (x, y)
+    }
+
+    /// West, south, east and north edge of the world in degrees.
+    pub fn bounds(&self) -> [f64; 4] {
+        let (west, south) = self.lon_lat(0.0, 0.0);
+        let (east, north) = self.lon_lat(self.world_size, self.world_size);
+
+        [west, south, east, north]
+    }
+
+    /// West, south, east and north edge of a EPSG:3857 tile in in-game meters.
+    pub fn tile_bounds(&self, col: u32, row: u32, zoom: u8) -> [f64; 4] {
+        let tile_size = TILE_SIZE_IN_PX as f64;
+        let (west, north) = pixel_to_lon_lat(col as f64 * tile_size, row as f64 * tile_size, zoom);
+        let (east, south) = pixel_to_lon_lat(
+            (col + 1) as f64 * tile_size,
+            (row + 1) as f64 * tile_size,
+            zoom,
+        );
+
+        let (west, south) = self.world_pos(west, south);
+        let (east, north) = self.world_pos(east, north);
+
+        [west, south, east, north]
+    }
+
+    /// Zoom levels at which the world roughly fits a single tile and at which
+    /// a tile pixel matches a pixel of `img`.
+    pub fn zoom_range(&self, img: &DynamicImage) -> (u8, u8) {
+        let equator = 2.0 * PI * EARTH_RADIUS * self.center_lat.to_radians().cos();
+        let meters_per_px = self.world_size / img.width() as f64;
+
+        let max_zoom = (equator / (TILE_SIZE_IN_PX as f64 * meters_per_px))
+            .log2()
+            .ceil()
+            .max(0.0) as u8;
+        let min_zoom = (equator / self.world_size).log2().floor().max(0.0) as u8;
+
+        (min_zoom.min(max_zoom), max_zoom)
+    }
+}
+
+fn lon_lat_to_pixel(lon: f64, lat: f64, zoom: u8) -> (f64, f64) {
+    let size = TILE_SIZE_IN_PX as f64 * 2f64.powi(zoom as i32);
+    let lat = lat.to_radians();
+
+    let x = (lon + 180.0) / 360.0 * size;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * size;
+
+    (x, y)
+}
+
+fn pixel_to_lon_lat(x: f64, y: f64, zoom: u8) -> (f64, f64) {
+    let size = TILE_SIZE_IN_PX as f64 * 2f64.powi(zoom as i32);
+
+    let lon = x / size * 360.0 - 180.0;
+    let lat = (PI * (1.0 - 2.0 * y / size)).sinh().atan().to_degrees();
+
+    (lon, lat)
+}
+
+/// Builds all EPSG:3857 tiles of the given zoom level which intersect the
+/// world. Pixels outside of the world are transparent.
+pub fn build_mercator_tile_set(
+    writer: &TileWriter,
+    img: &DynamicImage,
+    georef: &Georeference,
+    zoom: u8,
+) -> anyhow::Result<()> {
+    let zoom_span = tracing::debug_span!("lod", lod = zoom);
+    let _zoom_span = zoom_span.enter();
+
+    let [west, south, east, north] = georef.bounds();
+    let (min_x, min_y) = lon_lat_to_pixel(west, north, zoom);
+    let (max_x, max_y) = lon_lat_to_pixel(east, south, zoom);
+
+    let tile_size = TILE_SIZE_IN_PX as f64;
+    let cols = (min_x / tile_size).floor() as u32..(max_x / tile_size).ceil() as u32;
+    let rows = (min_y / tile_size).floor() as u32..(max_y / tile_size).ceil() as u32;
+
+    let tiles: Vec<(u32, u32)> = cols
+        .flat_map(|col| rows.clone().map(move |row| (col, row)))
+        .filter(|(col, row)| writer.includes_tile(*col, *row, zoom))
+        .collect();
+    if tiles.is_empty() {
+        return Ok(());
+    }
+
+    // scale the source down to the resolution of this zoom level first, so
+    // sampling single pixels doesn't alias
+    let world_px_w = (max_x - min_x).ceil() as u32;
+    let world_px_h = (max_y - min_y).ceil() as u32;
+    let source = if world_px_w < img.width() || world_px_h < img.height() {
+        imageops::resize(
+            img,
+            world_px_w.max(1),
+            world_px_h.max(1),
+            imageops::FilterType::Triangle,
+        )
+    } else {
+        img.to_rgba8()
+    };
+
+    if let Some(threads) = writer.encoder_threads() {
+        return write_on_encoder_threads(writer, threads, zoom, |queue| {
+            let _ = tiles.into_par_iter().try_for_each(|(col, row)| {
+                let _span = tracing::trace_span!(parent: &zoom_span, "tile", col, row).entered();
+                let tile = render_tile(&source, georef, col, row, zoom);
+                queue(DynamicImage::ImageRgba8(tile), col, row)
+                    .then_some(())
+                    .ok_or(())
+            });
+        });
+    }
+
+    tiles.into_par_iter().try_for_each(|(col, row)| {
+        let _span = tracing::trace_span!(parent: &zoom_span, "tile", col, row).entered();
+        let tile = render_tile(&source, georef, col, row, zoom);
+        writer.write_tile(&DynamicImage::ImageRgba8(tile), col, row, zoom)
+    })?;
+
+    Ok(())
+}
+
+fn render_tile(
+    source: &RgbaImage,
+    georef: &Georeference,
+    col: u32,
+    row: u32,
+    zoom: u8,
+) -> RgbaImage {
+    let (w, h) = source.dimensions();
+    let world_size = georef.world_size;
+
+    RgbaImage::from_fn(TILE_SIZE_IN_PX, TILE_SIZE_IN_PX, |px, py| {
+        let (lon, lat) = pixel_to_lon_lat(
+            (col * TILE_SIZE_IN_PX + px) as f64 + 0.5,
+            (row * TILE_SIZE_IN_PX + py) as f64 + 0.5,
+            zoom,
+        );
+        let (x, y) = georef.world_pos(lon, lat);
+
+        if x < 0.0 || y < 0.0 || x >= world_size || y >= world_size {
+            return Rgba([0, 0, 0, 0]);
+        }
+
+        let src_x = ((x / world_size * w as f64) as u32).min(w - 1);
+        let src_y = (((1.0 - y / world_size) * h as f64) as u32).min(h - 1);
+
+        *source.get_pixel(src_x, src_y)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lon_lat_to_pixel, pixel_to_lon_lat, Georeference};
+
+    #[test]
+    fn world_and_lon_lat_conversions_are_inverse() {
+        let georef = Georeference {
+            world_size: 10240.0,
+            center_lon: 16.5,
+            center_lat: 40.0,
+        };
+
+        let (lon, lat) = georef.lon_lat(1234.0, 9876.0);
+        let (x, y) = georef.world_pos(lon, lat);
+        assert!((x - 1234.0).abs() < 1e-6);
+        assert!((y - 9876.0).abs() < 1e-6);
+
+        let (lon, lat) = georef.lon_lat(5120.0, 5120.0);
+        assert!((lon - 16.5).abs() < 1e-9);
+        assert!((lat - 40.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mercator_pixel_conversions_are_inverse() {
+        let (x, y) = lon_lat_to_pixel(16.5, 40.0, 12);
+        let (lon, lat) = pixel_to_lon_lat(x, y, 12);
+        assert!((lon - 16.5).abs() < 1e-9);
+        assert!((lat - 40.0).abs() < 1e-9);
+
+        assert_eq!((128.0, 128.0), lon_lat_to_pixel(0.0, 0.0, 0));
+    }
+}
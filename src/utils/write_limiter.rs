@@ -0,0 +1,63 @@
+use std::sync::{Condvar, Mutex};
+
+/// Counting semaphore, which limits how many files are written at the same time.
+#[derive(Debug)]
+pub struct WriteLimiter {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+impl WriteLimiter {
+    pub fn new(max_concurrent_writes: usize) -> Self {
+        WriteLimiter {
+            available: Mutex::new(max_concurrent_writes.max(1)),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a write slot is free. The slot is given back, once the permit is dropped.
+    pub fn acquire(&self) -> WritePermit<'_> {
+        let mut available = self.available.lock().unwrap();
+        while *available == 0 {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= 1;
+
+        WritePermit { limiter: self }
+    }
+}
+
+pub struct WritePermit<'a> {
+    limiter: &'a WriteLimiter,
+}
+
+impl Drop for WritePermit<'_> {
+    fn drop(&mut self) {
+        *self.limiter.available.lock().unwrap() += 1;
+        self.limiter.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WriteLimiter;
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn never_exceeds_limit() {
+        let limiter = WriteLimiter::new(2);
+        let active = AtomicUsize::new(0);
+        let max_active = AtomicUsize::new(0);
+
+        (0..64).into_par_iter().for_each(|_| {
+            let _permit = limiter.acquire();
+            let now_active = active.fetch_add(1, Ordering::SeqCst) + 1;
+            max_active.fetch_max(now_active, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        assert!(max_active.load(Ordering::SeqCst) <= 2);
+    }
+}
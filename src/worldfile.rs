@@ -0,0 +1,71 @@
+use std::{fs, io::Error, path::Path};
+
+/*
+    World files (.pgw / .wld) hold the affine transformation from pixel to
+    map coordinates as six lines:
+
+    A   pixel width in map units
+    D   rotation (always 0 for us)
+    B   rotation (always 0 for us)
+    E   pixel height in map units (negative, because rows go southwards)
+    C   x of the center of the upper left pixel
+    F   y of the center of the upper left pixel
+
+    Map units are in-game meters with the origin in the south-west corner.
+*/
+fn world_file(width: u32, height: u32, world_size: u32) -> String {
+    let size = world_size as f64;
+    let pixel_width = size / width as f64;
+    let pixel_height = size / height as f64;
+
+    format!(
+        "{}\n0\n0\n{}\n{}\n{}\n",
+        pixel_width,
+        -pixel_height,
+        pixel_width / 2.0,
+        size - pixel_height / 2.0
+    )
+}
+
+const AUX_XML: &str = r#"<PAMDataset>
+  <SRS>LOCAL_CS["Arma 3 map",UNIT["metre",1],AXIS["Easting",EAST],AXIS["Northing",NORTH]]</SRS>
+</PAMDataset>
+"#;
+
+/// Writes `.pgw` and `.wld` sidecars next to the PNG at `image_path`, so GIS
+/// software places it at the correct in-game coordinates. With `aux_xml` an
+/// `.aux.xml` declaring the local coordinate system is written as well.
+pub fn write(
+    image_path: &Path,
+    width: u32,
+    height: u32,
+    world_size: u32,
+    aux_xml: bool,
+) -> Result<(), Error> {
+    let content = world_file(width, height, world_size);
+
+    fs::write(image_path.with_extension("pgw"), &content)?;
+    fs::write(image_path.with_extension("wld"), &content)?;
+
+    if aux_xml {
+        let mut aux_path = image_path.as_os_str().to_owned();
+        aux_path.push(".aux.xml");
+        fs::write(aux_path, AUX_XML)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::world_file;
+
+    #[test]
+    fn pixel_centers_are_referenced() {
+        assert_eq!("2\n0\n0\n-2\n1\n2047\n", world_file(1024, 1024, 2048));
+        assert_eq!(
+            "0.5\n0\n0\n-1\n0.25\n1023.5\n",
+            world_file(2048, 1024, 1024)
+        );
+    }
+}